@@ -0,0 +1,187 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, VecDeque},
+    sync::Arc,
+};
+
+use nohash_hasher::IntMap;
+
+use crate::{
+    export::Layout,
+    model::{Node, Relation},
+};
+
+/// Index based, undirected view of a graph used by the analysis and layout algorithms.
+///
+/// Nodes are addressed by their position in the slice the adjacency was built from. Relation
+/// weights are treated as edge lengths.
+#[derive(Clone, Debug, Default)]
+pub struct Adjacency {
+    offsets: Vec<usize>,
+    targets: Vec<(usize, f32)>,
+}
+
+impl Adjacency {
+    /// Builds the adjacency from `(from, to, weight)` triples of node indices.
+    pub fn from_edges<I: IntoIterator<Item = (usize, usize, f32)>>(len: usize, edges: I) -> Self {
+        let mut lists: Vec<Vec<(usize, f32)>> = vec![Vec::new(); len];
+        for (from, to, weight) in edges {
+            lists[from].push((to, weight));
+            if from != to {
+                lists[to].push((from, weight));
+            }
+        }
+        let mut offsets = Vec::with_capacity(len + 1);
+        offsets.push(0);
+        let mut targets = Vec::new();
+        for list in lists {
+            targets.extend(list);
+            offsets.push(targets.len());
+        }
+        Self { offsets, targets }
+    }
+
+    pub fn from_graph(nodes: &[Arc<Node>], relations: &[Arc<Relation>]) -> Self {
+        let index: IntMap<usize, usize> =
+            nodes.iter().enumerate().map(|(i, e)| (e.id(), i)).collect();
+        let edges = relations
+            .iter()
+            .filter_map(|e| Some((*index.get(&e.from.id())?, *index.get(&e.to.id())?, e.weight)));
+        Self::from_edges(nodes.len(), edges)
+    }
+
+    /// Edges referring to unknown node ids are ignored.
+    pub fn from_layout(layout: &Layout) -> Self {
+        let index = layout.index();
+        let edges = layout
+            .edges
+            .iter()
+            .filter_map(|e| Some((*index.get(&e.from)?, *index.get(&e.to)?, e.weight)));
+        Self::from_edges(layout.nodes.len(), edges)
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Neighbours of a node together with the weight of the connecting edge.
+    pub fn neighbours(&self, node: usize) -> &[(usize, f32)] {
+        &self.targets[self.offsets[node]..self.offsets[node + 1]]
+    }
+
+    pub fn degree(&self, node: usize) -> usize {
+        self.offsets[node + 1] - self.offsets[node]
+    }
+
+    /// Number of hops from `source` to every node, `None` for unreachable ones.
+    pub fn hops(&self, source: usize) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.len()];
+        let mut queue = VecDeque::from([source]);
+        distances[source] = Some(0);
+        while let Some(current) = queue.pop_front() {
+            let next = distances[current].unwrap() + 1;
+            for &(neighbour, _) in self.neighbours(current) {
+                if distances[neighbour].is_none() {
+                    distances[neighbour] = Some(next);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Weighted distances from `source` to every node, infinite for unreachable ones.
+    pub fn distances(&self, source: usize) -> Vec<f32> {
+        self.shortest_paths(source).0
+    }
+
+    /// Dijkstra from `source`, returning the distances and the predecessor of every node on its
+    /// shortest path.
+    pub fn shortest_paths(&self, source: usize) -> (Vec<f32>, Vec<Option<usize>>) {
+        let mut distances = vec![f32::INFINITY; self.len()];
+        let mut predecessors = vec![None; self.len()];
+        let mut heap = BinaryHeap::from([Candidate {
+            distance: 0.0,
+            node: source,
+        }]);
+        distances[source] = 0.0;
+        while let Some(Candidate { distance, node }) = heap.pop() {
+            if distance > distances[node] {
+                continue;
+            }
+            for &(neighbour, weight) in self.neighbours(node) {
+                let next = distance + weight.max(0.0);
+                if next < distances[neighbour] {
+                    distances[neighbour] = next;
+                    predecessors[neighbour] = Some(node);
+                    heap.push(Candidate {
+                        distance: next,
+                        node: neighbour,
+                    });
+                }
+            }
+        }
+        (distances, predecessors)
+    }
+
+    /// Length and nodes of a shortest path from `source` to `target`, both included. `None` if
+    /// `target` cannot be reached.
+    pub fn path(&self, source: usize, target: usize) -> Option<(f32, Vec<usize>)> {
+        let (distances, predecessors) = self.shortest_paths(source);
+        if distances[target].is_infinite() {
+            return None;
+        }
+        let mut path = vec![target];
+        while let Some(previous) = predecessors[*path.last().unwrap()] {
+            path.push(previous);
+        }
+        path.reverse();
+        Some((distances[target], path))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+struct Candidate {
+    distance: f32,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance
+            .total_cmp(&self.distance)
+            .then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Adjacency;
+
+    #[test]
+    fn weighted_and_hop_distances() {
+        // 0 - 1 - 2 with a long shortcut 0 - 2, 3 is isolated
+        let adjacency = Adjacency::from_edges(4, [(0, 1, 1.0), (1, 2, 1.5), (0, 2, 4.0)]);
+        assert_eq!(adjacency.hops(0), [Some(0), Some(1), Some(1), None]);
+        let distances = adjacency.distances(0);
+        assert_eq!(&distances[..3], [0.0, 1.0, 2.5]);
+        assert!(distances[3].is_infinite());
+
+        assert_eq!(adjacency.path(0, 2), Some((2.5, vec![0, 1, 2])));
+        assert_eq!(adjacency.path(2, 2), Some((0.0, vec![2])));
+        assert_eq!(adjacency.path(0, 3), None);
+    }
+}
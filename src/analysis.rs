@@ -0,0 +1,361 @@
+//! Centrality of the nodes of a graph.
+//!
+//! The path based measures, betweenness and closeness, treat relation weights as lengths like
+//! the rest of [`Adjacency`]. The others treat them as the strength of a connection, like the
+//! [community detection](crate::community).
+
+use std::{cmp::Ordering, collections::BinaryHeap, sync::Arc};
+
+use crate::{adjacency::Adjacency, export::Layout, model::Node, sim::map_ranges};
+
+/// Probability of following a relation instead of jumping to a random node in PageRank.
+const DAMPING: f32 = 0.85;
+/// Upper bound of the iterations of PageRank and the eigenvector centrality.
+const MAX_ITERATIONS: usize = 1000;
+/// Summed change of all values below which the iterations stop.
+const TOLERANCE: f32 = 1.0E-6;
+/// Radii of the nodes with the lowest and highest centrality when it drives the radius.
+pub const MIN_RADIUS: f32 = 1.0;
+pub const MAX_RADIUS: f32 = 10.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Centrality {
+    /// Number of relations.
+    Degree,
+    /// Summed weight of the relations.
+    WeightedDegree,
+    /// Share of the shortest paths between other nodes that pass through the node.
+    Betweenness,
+    /// Inverse of the mean distance to the reachable nodes, scaled down by the share of nodes
+    /// reachable at all.
+    Closeness,
+    /// Probability of a random walk along the relations to be at the node.
+    PageRank,
+    /// Importance derived from the importance of the neighbours.
+    Eigenvector,
+}
+
+impl Centrality {
+    pub const ALL: [Centrality; 6] = [
+        Centrality::Degree,
+        Centrality::WeightedDegree,
+        Centrality::Betweenness,
+        Centrality::Closeness,
+        Centrality::PageRank,
+        Centrality::Eigenvector,
+    ];
+
+    /// Name of the column the centrality is exported as.
+    pub fn name(self) -> &'static str {
+        match self {
+            Centrality::Degree => "degree",
+            Centrality::WeightedDegree => "weighted_degree",
+            Centrality::Betweenness => "betweenness",
+            Centrality::Closeness => "closeness",
+            Centrality::PageRank => "pagerank",
+            Centrality::Eigenvector => "eigenvector",
+        }
+    }
+
+    pub fn compute(self, adjacency: &Adjacency) -> Vec<f32> {
+        match self {
+            Centrality::Degree => degree(adjacency),
+            Centrality::WeightedDegree => weighted_degree(adjacency),
+            Centrality::Betweenness => betweenness(adjacency),
+            Centrality::Closeness => closeness(adjacency),
+            Centrality::PageRank => pagerank(adjacency),
+            Centrality::Eigenvector => eigenvector(adjacency),
+        }
+    }
+}
+
+pub fn degree(adjacency: &Adjacency) -> Vec<f32> {
+    (0..adjacency.len())
+        .map(|i| adjacency.degree(i) as f32)
+        .collect()
+}
+
+pub fn weighted_degree(adjacency: &Adjacency) -> Vec<f32> {
+    (0..adjacency.len())
+        .map(|i| adjacency.neighbours(i).iter().map(|(_, w)| w.abs()).sum())
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Visit {
+    distance: f32,
+    node: usize,
+}
+
+impl Eq for Visit {}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance
+            .total_cmp(&self.distance)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Shortest paths from one source, keeping every predecessor on a shortest path.
+struct ShortestPaths {
+    distance: Vec<f32>,
+    /// Number of shortest paths from the source.
+    paths: Vec<f32>,
+    predecessors: Vec<Vec<usize>>,
+    /// Reached nodes by growing distance.
+    order: Vec<usize>,
+}
+
+fn shortest_paths(adjacency: &Adjacency, source: usize) -> ShortestPaths {
+    let n = adjacency.len();
+    let mut result = ShortestPaths {
+        distance: vec![f32::INFINITY; n],
+        paths: vec![0.0; n],
+        predecessors: vec![Vec::new(); n],
+        order: Vec::new(),
+    };
+    let mut done = vec![false; n];
+    result.distance[source] = 0.0;
+    result.paths[source] = 1.0;
+    let mut heap = BinaryHeap::from([Visit {
+        distance: 0.0,
+        node: source,
+    }]);
+    while let Some(Visit { distance, node }) = heap.pop() {
+        if done[node] {
+            continue;
+        }
+        done[node] = true;
+        result.order.push(node);
+        for &(neighbour, weight) in adjacency.neighbours(node) {
+            let next = distance + weight.abs();
+            let known = result.distance[neighbour];
+            // Paths of equal length differ by rounding when summed in another order.
+            let tie = known.is_finite() && (next - known).abs() <= 1.0E-6 * known.max(1.0);
+            if tie && !done[neighbour] {
+                result.paths[neighbour] += result.paths[node];
+                result.predecessors[neighbour].push(node);
+            } else if next < known {
+                result.distance[neighbour] = next;
+                result.paths[neighbour] = result.paths[node];
+                result.predecessors[neighbour] = vec![node];
+                heap.push(Visit {
+                    distance: next,
+                    node: neighbour,
+                });
+            }
+        }
+    }
+    result
+}
+
+/// Betweenness by Brandes' algorithm, normalized by the number of pairs of other nodes. The
+/// sources are split among the threads.
+pub fn betweenness(adjacency: &Adjacency) -> Vec<f32> {
+    let n = adjacency.len();
+    let partial = map_ranges(n, |range| {
+        let mut centrality = vec![0.0f32; n];
+        let mut dependency = vec![0.0f32; n];
+        for source in range {
+            let paths = shortest_paths(adjacency, source);
+            for &node in &paths.order {
+                dependency[node] = 0.0;
+            }
+            for &node in paths.order.iter().rev() {
+                for &predecessor in &paths.predecessors[node] {
+                    dependency[predecessor] +=
+                        paths.paths[predecessor] / paths.paths[node] * (1.0 + dependency[node]);
+                }
+                if node != source {
+                    centrality[node] += dependency[node];
+                }
+            }
+        }
+        centrality
+    });
+    let scale = if n > 2 {
+        1.0 / ((n - 1) * (n - 2)) as f32
+    } else {
+        0.0
+    };
+    partial.into_iter().fold(vec![0.0; n], |mut acc, part| {
+        for (sum, value) in acc.iter_mut().zip(part) {
+            *sum += value * scale;
+        }
+        acc
+    })
+}
+
+/// Closeness after Wasserman and Faust, which stays comparable in disconnected graphs.
+pub fn closeness(adjacency: &Adjacency) -> Vec<f32> {
+    let n = adjacency.len();
+    map_ranges(n, |range| {
+        range
+            .map(|source| {
+                let (total, reached) = shortest_paths(adjacency, source)
+                    .distance
+                    .iter()
+                    .filter(|e| e.is_finite())
+                    .fold((0.0f32, 0usize), |acc, e| (acc.0 + e, acc.1 + 1));
+                if total <= 0.0 || n < 2 {
+                    return 0.0;
+                }
+                let others = (reached - 1) as f32;
+                others / total * others / (n - 1) as f32
+            })
+            .collect::<Vec<f32>>()
+    })
+    .concat()
+}
+
+/// Repeats `step` on a uniform start until the values settle, summing to one.
+fn iterate(n: usize, step: impl Fn(&[f32]) -> Vec<f32>) -> Vec<f32> {
+    let mut values = vec![1.0 / n as f32; n];
+    for _ in 0..MAX_ITERATIONS {
+        let mut next = step(&values);
+        let total: f32 = next.iter().sum();
+        if total > 0.0 {
+            next.iter_mut().for_each(|e| *e /= total);
+        }
+        let change: f32 = next.iter().zip(&values).map(|(a, b)| (a - b).abs()).sum();
+        values = next;
+        if change < TOLERANCE {
+            break;
+        }
+    }
+    values
+}
+
+/// PageRank with relations walked in both directions, in proportion to their weight. The values
+/// sum to one.
+pub fn pagerank(adjacency: &Adjacency) -> Vec<f32> {
+    let n = adjacency.len();
+    let strength = weighted_degree(adjacency);
+    iterate(n, |rank| {
+        // Nodes without relations hand their rank to all nodes alike.
+        let dangling: f32 = (0..n)
+            .filter(|e| strength[*e] <= 0.0)
+            .map(|e| rank[e])
+            .sum();
+        let base = ((1.0 - DAMPING) + DAMPING * dangling) / n as f32;
+        let mut next = vec![base; n];
+        for (i, &rank) in rank.iter().enumerate() {
+            if strength[i] > 0.0 {
+                for &(j, w) in adjacency.neighbours(i) {
+                    next[j] += DAMPING * rank * w.abs() / strength[i];
+                }
+            }
+        }
+        next
+    })
+}
+
+/// Eigenvector centrality by power iteration, scaled so the largest value is one.
+///
+/// Iterating with the node itself included keeps bipartite graphs from oscillating without
+/// changing the eigenvector.
+pub fn eigenvector(adjacency: &Adjacency) -> Vec<f32> {
+    let values = iterate(adjacency.len(), |values| {
+        (0..adjacency.len())
+            .map(|i| {
+                values[i]
+                    + adjacency
+                        .neighbours(i)
+                        .iter()
+                        .map(|&(j, w)| values[j] * w.abs())
+                        .sum::<f32>()
+            })
+            .collect()
+    });
+    let largest = values.iter().copied().fold(0.0, f32::max);
+    if largest > 0.0 {
+        values.into_iter().map(|e| e / largest).collect()
+    } else {
+        values
+    }
+}
+
+/// Radii growing with the square root of the values, so the area of a node shows its value.
+pub fn radii(values: &[f32]) -> Vec<f32> {
+    let largest = values.iter().copied().fold(0.0, f32::max);
+    values
+        .iter()
+        .map(|e| {
+            let share = if largest > 0.0 {
+                (e.max(0.0) / largest).sqrt()
+            } else {
+                0.0
+            };
+            MIN_RADIUS + (MAX_RADIUS - MIN_RADIUS) * share
+        })
+        .collect()
+}
+
+/// Adds the values as the attribute `name` of the nodes in the layout, matched by their id.
+pub fn annotate(layout: &mut Layout, name: &str, nodes: &[Arc<Node>], values: &[f32]) {
+    let index = layout.index();
+    for (node, value) in nodes.iter().zip(values) {
+        if let Some(&i) = index.get(&node.id()) {
+            layout.nodes[i]
+                .attributes
+                .insert(name.to_string(), value.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adjacency::Adjacency;
+
+    use super::{betweenness, closeness, eigenvector, pagerank, radii, weighted_degree};
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1.0E-4)
+    }
+
+    #[test]
+    fn star_centre_dominates() {
+        let star = Adjacency::from_edges(5, (1..5).map(|e| (0, e, 1.0)));
+        assert!(close(&betweenness(&star), &[1.0, 0.0, 0.0, 0.0, 0.0]));
+        // The centre reaches everything in one hop, the leaves need two hops to the others.
+        assert!(close(
+            &closeness(&star),
+            &[1.0, 4.0 / 7.0, 4.0 / 7.0, 4.0 / 7.0, 4.0 / 7.0]
+        ));
+
+        let rank = pagerank(&star);
+        assert!((rank.iter().sum::<f32>() - 1.0).abs() < 1.0E-4);
+        assert!(rank[1..].iter().all(|e| *e < rank[0]));
+        // In a star the centre has twice the eigenvector centrality of a leaf.
+        assert!(close(&eigenvector(&star), &[1.0, 0.5, 0.5, 0.5, 0.5]));
+    }
+
+    #[test]
+    fn weights_count_as_lengths_and_strengths() {
+        // Two routes from 0 to 3, the one through 1 is shorter.
+        let diamond =
+            Adjacency::from_edges(4, [(0, 1, 1.0), (1, 3, 1.0), (0, 2, 2.0), (2, 3, 2.0)]);
+        let centrality = betweenness(&diamond);
+        assert!(centrality[1] > 0.0);
+        assert_eq!(centrality[2], 0.0);
+        assert_eq!(weighted_degree(&diamond), vec![3.0, 2.0, 4.0, 3.0]);
+
+        // Paths of equal length share the dependency.
+        let square = Adjacency::from_edges(4, [(0, 1, 1.0), (1, 3, 1.0), (0, 2, 1.0), (2, 3, 1.0)]);
+        let centrality = betweenness(&square);
+        assert!((centrality[1] - centrality[2]).abs() < 1.0E-6 && centrality[1] > 0.0);
+    }
+
+    #[test]
+    fn radius_grows_with_area() {
+        assert_eq!(radii(&[0.0, 1.0, 4.0]), vec![1.0, 5.5, 10.0]);
+    }
+}
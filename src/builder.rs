@@ -0,0 +1,341 @@
+use std::{collections::BTreeMap, error::Error, fmt, sync::Arc};
+
+use nohash_hasher::IntMap;
+use rand::{rngs::SmallRng, Rng, RngCore, SeedableRng};
+
+use crate::{
+    export::Layout,
+    model::{Graph, Node, Relation},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphError {
+    DuplicateNode(usize),
+    UnknownNode(usize),
+    UnknownRelation(usize, usize),
+    DuplicateRelation(usize),
+    SelfLoop(usize),
+    InvalidWeight(f32),
+    InvalidNodeWeight(f32),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::DuplicateNode(id) => write!(f, "node {id} was added twice"),
+            GraphError::UnknownNode(id) => write!(f, "node {id} does not exist"),
+            GraphError::UnknownRelation(from, to) => {
+                write!(f, "there is no relation between {from} and {to}")
+            }
+            GraphError::DuplicateRelation(id) => write!(f, "relation {id} was added twice"),
+            GraphError::SelfLoop(id) => write!(f, "node {id} cannot be related to itself"),
+            GraphError::InvalidWeight(weight) => {
+                write!(f, "weight {weight} is not a non-negative finite number")
+            }
+            GraphError::InvalidNodeWeight(weight) => {
+                write!(f, "node weight {weight} is not a positive finite number")
+            }
+        }
+    }
+}
+
+impl Error for GraphError {}
+
+impl From<GraphError> for std::io::Error {
+    fn from(e: GraphError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Side length of the square randomly placed nodes start in.
+pub const INITIAL_EXTENT: f32 = 100.0;
+
+pub(crate) fn generate_coordinate<R: RngCore>(mut rng: R) -> f32 {
+    loop {
+        let tmp: f32 = rng.gen_range(0.0..INITIAL_EXTENT);
+        if tmp.is_normal() {
+            return tmp;
+        }
+    }
+}
+
+struct NodeSpec {
+    id: usize,
+    x: f32,
+    y: f32,
+    weight: f32,
+    label: Option<String>,
+    radius: Option<f32>,
+    attributes: BTreeMap<String, String>,
+}
+
+struct EdgeSpec {
+    from: usize,
+    to: usize,
+    weight: f32,
+}
+
+/// Assembles a [`Graph`] from plain ids, taking care of wiring up the relations.
+///
+/// Nodes added without an explicit position are placed randomly, using a fixed seed so the same
+/// input always yields the same layout.
+pub struct GraphBuilder {
+    nodes: Vec<NodeSpec>,
+    index: IntMap<usize, usize>,
+    edges: Vec<EdgeSpec>,
+    rng: SmallRng,
+}
+
+impl Default for GraphBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GraphBuilder {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            index: IntMap::default(),
+            edges: Vec::new(),
+            rng: SmallRng::from_seed([0u8; 32]),
+        }
+    }
+
+    /// Starts from a previously exported layout, keeping its node positions.
+    pub fn from_layout(layout: &Layout) -> Result<Self, GraphError> {
+        let mut builder = Self::new();
+        for node in &layout.nodes {
+            builder
+                .add_node_at(node.id, node.x, node.y, node.weight)?
+                .attributes(node.id, node.attributes.clone())?;
+            let spec = builder.node_mut(node.id)?;
+            spec.label = node.label.clone();
+            spec.radius = node.radius;
+        }
+        for edge in &layout.edges {
+            builder.add_edge(edge.from, edge.to, edge.weight)?;
+        }
+        Ok(builder)
+    }
+
+    pub fn add_node(&mut self, id: usize, weight: f32) -> Result<&mut Self, GraphError> {
+        let (x, y) = (
+            generate_coordinate(&mut self.rng),
+            generate_coordinate(&mut self.rng),
+        );
+        self.add_node_at(id, x, y, weight)
+    }
+
+    pub fn add_node_at(
+        &mut self,
+        id: usize,
+        x: f32,
+        y: f32,
+        weight: f32,
+    ) -> Result<&mut Self, GraphError> {
+        check_node_weight(weight)?;
+        if self.index.contains_key(&id) {
+            return Err(GraphError::DuplicateNode(id));
+        }
+        self.index.insert(id, self.nodes.len());
+        self.nodes.push(NodeSpec {
+            id,
+            x,
+            y,
+            weight,
+            label: None,
+            radius: None,
+            attributes: BTreeMap::new(),
+        });
+        Ok(self)
+    }
+
+    pub fn label(&mut self, id: usize, label: impl Into<String>) -> Result<&mut Self, GraphError> {
+        self.node_mut(id)?.label = Some(label.into());
+        Ok(self)
+    }
+
+    pub fn attribute(
+        &mut self,
+        id: usize,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<&mut Self, GraphError> {
+        self.node_mut(id)?
+            .attributes
+            .insert(key.into(), value.into());
+        Ok(self)
+    }
+
+    pub fn attributes(
+        &mut self,
+        id: usize,
+        attributes: BTreeMap<String, String>,
+    ) -> Result<&mut Self, GraphError> {
+        self.node_mut(id)?.attributes.extend(attributes);
+        Ok(self)
+    }
+
+    /// Adds a relation between two different, previously added nodes.
+    pub fn add_edge(
+        &mut self,
+        from: usize,
+        to: usize,
+        weight: f32,
+    ) -> Result<&mut Self, GraphError> {
+        check_relation(from, to, weight)?;
+        for id in [from, to] {
+            if !self.index.contains_key(&id) {
+                return Err(GraphError::UnknownNode(id));
+            }
+        }
+        self.edges.push(EdgeSpec { from, to, weight });
+        Ok(self)
+    }
+
+    pub fn contains_node(&self, id: usize) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    pub fn build(self) -> Graph {
+        let nodes: Vec<Arc<Node>> = self
+            .nodes
+            .into_iter()
+            .map(|e| {
+                let node = Node::new(e.id, e.x, e.y, e.weight)
+                    .with_label(e.label)
+                    .with_attributes(e.attributes);
+                node.set_radius(e.radius);
+                node
+            })
+            .map(Arc::new)
+            .collect();
+        let relations = self
+            .edges
+            .into_iter()
+            .map(|e| {
+                let from = nodes[self.index[&e.from]].clone();
+                let to = nodes[self.index[&e.to]].clone();
+                Relation::connect(e.weight, from, to)
+            })
+            .collect();
+        Graph::new(nodes, relations)
+    }
+
+    fn node_mut(&mut self, id: usize) -> Result<&mut NodeSpec, GraphError> {
+        match self.index.get(&id) {
+            Some(&i) => Ok(&mut self.nodes[i]),
+            None => Err(GraphError::UnknownNode(id)),
+        }
+    }
+}
+
+/// Node weights scale the repulsion and the radius, so they have to be positive.
+pub(crate) fn check_node_weight(weight: f32) -> Result<(), GraphError> {
+    if weight.is_finite() && weight > 0.0 {
+        Ok(())
+    } else {
+        Err(GraphError::InvalidNodeWeight(weight))
+    }
+}
+
+/// Relation weights are spring stiffnesses. The force only sees their square, so a negative
+/// weight would pull like a positive one while paths and metrics read it differently.
+pub(crate) fn check_weight(weight: f32) -> Result<(), GraphError> {
+    if weight.is_finite() && weight >= 0.0 {
+        Ok(())
+    } else {
+        Err(GraphError::InvalidWeight(weight))
+    }
+}
+
+/// A relation of a node to itself has no direction to pull in, its spring force would be NaN.
+pub(crate) fn check_relation(from: usize, to: usize, weight: f32) -> Result<(), GraphError> {
+    if from == to {
+        return Err(GraphError::SelfLoop(from));
+    }
+    check_weight(weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{model::Node, sim::LayoutParams};
+
+    use super::{GraphBuilder, GraphError};
+
+    #[test]
+    fn rejects_invalid_ids() {
+        let mut builder = GraphBuilder::new();
+        builder.add_node(1, 1.0).unwrap();
+        assert_eq!(
+            builder.add_node(1, 1.0).err(),
+            Some(GraphError::DuplicateNode(1))
+        );
+        assert_eq!(
+            builder.add_edge(1, 2, 1.0).err(),
+            Some(GraphError::UnknownNode(2))
+        );
+        assert_eq!(
+            builder.label(3, "missing").err(),
+            Some(GraphError::UnknownNode(3))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_weights_and_self_loops() {
+        let mut builder = GraphBuilder::new();
+        builder.add_node(1, 1.0).unwrap();
+        assert_eq!(
+            builder.add_node(2, 0.0).err(),
+            Some(GraphError::InvalidNodeWeight(0.0))
+        );
+        assert_eq!(
+            builder.add_node(3, -1.0).err(),
+            Some(GraphError::InvalidNodeWeight(-1.0))
+        );
+        assert_eq!(
+            builder.add_edge(1, 1, 1.0).err(),
+            Some(GraphError::SelfLoop(1))
+        );
+        builder.add_node(2, 1.0).unwrap();
+        assert!(matches!(
+            builder.add_edge(1, 2, f32::NAN).err(),
+            Some(GraphError::InvalidWeight(_))
+        ));
+        assert_eq!(
+            builder.add_edge(1, 2, -1.0).err(),
+            Some(GraphError::InvalidWeight(-1.0))
+        );
+        assert!(builder.add_edge(1, 2, 0.0).is_ok());
+    }
+
+    #[test]
+    fn builds_registered_relations() {
+        let mut builder = GraphBuilder::new();
+        builder
+            .add_node_at(1, 0.0, 0.0, 1.0)
+            .unwrap()
+            .add_node_at(2, 4.0, 0.0, 1.0)
+            .unwrap()
+            .label(2, "two")
+            .unwrap()
+            .add_edge(1, 2, 1.0)
+            .unwrap();
+        let graph = builder.build();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.relations.len(), 1);
+        assert_eq!(graph.nodes[1].label.as_deref(), Some("two"));
+
+        // The spring pulls the first node towards the second one.
+        let params = LayoutParams {
+            spring_scale: 1.0,
+            coloumb_scale: 0.0,
+            time_delta: 1.0,
+            ..LayoutParams::default()
+        };
+        let moved =
+            graph.nodes[0].calc_new_position(&graph.nodes, Node::centre(&graph.nodes), &params);
+        assert!(moved.x > 0.0);
+    }
+}
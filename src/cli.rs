@@ -1,11 +1,238 @@
+use graph_visualizer::{
+    analysis::Centrality,
+    community::Method,
+    components::Packing,
+    filter::Predicate,
+    geo::{Projection, ANCHOR_STRENGTH},
+    labels::Priority,
+    sim::{
+        COLLISION_SCALE, COLOUMB_SCALE, COMMUNITY_SCALE, GRAVITY_SCALE, SPING_SCALE, TIME_DELTA,
+    },
+    tune::Objective,
+};
 use std::path::PathBuf;
-use crate::sim::{TIME_DELTA, SPING_SCALE, COLOUMB_SCALE};
 
-use clap::Parser;
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Static SVG image
+    Svg,
+    /// Self-contained interactive HTML viewer
+    Html,
+    /// Node positions and relations as JSON
+    Json,
+    /// Node positions and attributes as CSV
+    Csv,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Algorithm {
+    /// Coloumb and spring force simulation
+    Force,
+    /// Stress majorization against the shortest path distances
+    Stress,
+    /// Kamada-Kawai spring energy, for graphs of up to a few hundred nodes
+    KamadaKawai,
+    /// Force simulation on a hierarchy of coarsened graphs, for very large graphs
+    Multilevel,
+    /// Layers following the direction of the relations, for dependency graphs
+    Layered,
+    /// All nodes on one circle, ordered to reduce crossings
+    Circular,
+    /// Breadth first tree around `--root` with one ring per depth
+    Radial,
+    /// Projected positions, nudged apart where nodes overlap, requires `--projection`
+    Geographic,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Placement {
+    /// Random positions
+    Random,
+    /// Positions of the circular layout
+    Circular,
+    /// Positions of the radial tree layout
+    Radial,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TuneObjective {
+    Stress,
+    Crossings,
+}
+
+impl From<TuneObjective> for Objective {
+    fn from(o: TuneObjective) -> Self {
+        match o {
+            TuneObjective::Stress => Objective::Stress,
+            TuneObjective::Crossings => Objective::EdgeCrossings,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MapProjection {
+    /// Web Mercator
+    Mercator,
+    /// Longitude and latitude used as is
+    Equirectangular,
+}
+
+impl From<MapProjection> for Projection {
+    fn from(projection: MapProjection) -> Self {
+        match projection {
+            MapProjection::Mercator => Projection::Mercator,
+            MapProjection::Equirectangular => Projection::Equirectangular,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ComponentPacking {
+    /// Bounding boxes in rows
+    Shelf,
+    /// Rasterized shapes fitted into each other's holes
+    Polyomino,
+}
+
+impl From<ComponentPacking> for Packing {
+    fn from(packing: ComponentPacking) -> Self {
+        match packing {
+            ComponentPacking::Shelf => Packing::Shelf,
+            ComponentPacking::Polyomino => Packing::Polyomino,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CommunityMethod {
+    /// Modularity optimization, finds finer and more stable communities
+    Louvain,
+    /// Majority vote of the neighbours, faster on very large graphs
+    LabelPropagation,
+}
+
+impl From<CommunityMethod> for Method {
+    fn from(method: CommunityMethod) -> Self {
+        match method {
+            CommunityMethod::Louvain => Method::Louvain,
+            CommunityMethod::LabelPropagation => Method::LabelPropagation,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CentralityKind {
+    /// Number of relations
+    Degree,
+    /// Summed weight of the relations
+    WeightedDegree,
+    /// Share of shortest paths passing through the node
+    Betweenness,
+    /// Inverse mean distance to the other nodes
+    Closeness,
+    /// Probability of a random walk to be at the node
+    Pagerank,
+    /// Importance derived from the importance of the neighbours
+    Eigenvector,
+}
+
+impl From<CentralityKind> for Centrality {
+    fn from(kind: CentralityKind) -> Self {
+        match kind {
+            CentralityKind::Degree => Centrality::Degree,
+            CentralityKind::WeightedDegree => Centrality::WeightedDegree,
+            CentralityKind::Betweenness => Centrality::Betweenness,
+            CentralityKind::Closeness => Centrality::Closeness,
+            CentralityKind::Pagerank => Centrality::PageRank,
+            CentralityKind::Eigenvector => Centrality::Eigenvector,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LabelPriority {
+    /// Heavier nodes first
+    Weight,
+    /// Nodes with more relations first
+    Degree,
+}
+
+impl From<LabelPriority> for Priority {
+    fn from(priority: LabelPriority) -> Self {
+        match priority {
+            LabelPriority::Weight => Priority::Weight,
+            LabelPriority::Degree => Priority::Degree,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Table,
+    Json,
+}
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about)]
+#[clap(author, version, about, args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    #[clap(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print quality metrics of a layout previously written as JSON
+    Metrics {
+        /// Location of the layout file
+        layout: PathBuf,
+        #[clap(short, long, value_enum, default_value_t = ReportFormat::Table)]
+        format: ReportFormat,
+    },
+    /// Keep a force simulation running and change it by JSON commands read line by line from
+    /// stdin, writing the requested snapshots to stdout
+    Stream(StreamArgs),
+    /// Keep a force simulation running and serve its positions, an SVG of it and a stream of
+    /// changes over HTTP on localhost
+    Serve {
+        /// Port to listen on, 0 picks a free one
+        #[clap(long, default_value_t = 8080)]
+        port: u16,
+        #[clap(flatten)]
+        stream: StreamArgs,
+    },
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct StreamArgs {
+    /// Location of the file containing the nodes to start with, starts empty without one
+    #[clap(short, long, requires = "relations-file")]
+    pub nodes_file: Option<PathBuf>,
+    /// Location of the file containing the relations to start with
+    #[clap(short, long, requires = "nodes-file")]
+    pub relations_file: Option<PathBuf>,
+    /// Simulation steps between two checks for new commands
+    #[clap(long, default_value_t = 10)]
+    pub steps_between: usize,
+    /// Scaling factor of the springs
+    #[clap(short, long, default_value_t = SPING_SCALE)]
+    pub spring: f32,
+    /// Scaling factor of the coloumb force
+    #[clap(short, long, default_value_t = COLOUMB_SCALE)]
+    pub coloumb: f32,
+    /// Time delta in each computation step
+    #[clap(short, long, default_value_t = TIME_DELTA)]
+    pub time: f32,
+    /// Scaling factor of the pull towards the centre of mass
+    #[clap(short, long, default_value_t = GRAVITY_SCALE)]
+    pub gravity: f32,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct RunArgs {
     /// Location of the file containing the nodes
     #[clap(short, long, default_value = "locations.csv")]
     pub nodes_file: PathBuf,
@@ -15,6 +242,66 @@ pub struct Args {
     /// Location of the file containing the generated csv
     #[clap(short, long, default_value = "out.csv")]
     pub out: PathBuf,
+    /// Format of the generated output
+    #[clap(short, long, value_enum, default_value_t = Format::Svg)]
+    pub format: Format,
+    /// Layout algorithm, `--steps` bounds its iterations
+    #[clap(short, long, value_enum, default_value_t = Algorithm::Force)]
+    pub algorithm: Algorithm,
+    /// Keep only the nodes at most `--ego-hops` relations away from this node id
+    #[clap(long)]
+    pub ego: Option<usize>,
+    #[clap(long, default_value_t = 1, requires = "ego")]
+    pub ego_hops: usize,
+    /// Drop relations with a lower weight
+    #[clap(long)]
+    pub min_weight: Option<f32>,
+    /// Drop nodes with fewer relations, repeatedly until all remaining nodes have as many
+    #[clap(long)]
+    pub min_degree: Option<usize>,
+    /// Keep only the largest connected component
+    #[clap(long)]
+    pub largest_component: bool,
+    /// Keep only nodes whose attributes match, like `zone=north` or `platforms>=4`
+    #[clap(long = "where", value_parser)]
+    pub predicates: Vec<Predicate>,
+    /// Merge all nodes sharing a value of this attribute into one node labelled with the value
+    #[clap(long, value_name = "ATTRIBUTE")]
+    pub collapse: Option<String>,
+    /// Lay out chains of nodes with two neighbours as single relations, then spread the nodes
+    /// evenly along them
+    #[clap(long)]
+    pub contract_chains: bool,
+    /// Initial positions the layout algorithm starts from
+    #[clap(long, value_enum, default_value_t = Placement::Random)]
+    pub initial: Placement,
+    /// Id of the node in the centre of the radial layout, defaults to the one of highest degree
+    #[clap(long)]
+    pub root: Option<usize>,
+    /// Place nodes with `lat` and `lon` columns by this map projection
+    #[clap(long, value_enum, conflicts_with = "initial")]
+    pub projection: Option<MapProjection>,
+    /// Strength of the force pulling projected nodes back to their position
+    #[clap(long, requires = "projection")]
+    pub anchor: Option<f32>,
+    /// JSON layout of an earlier version of the graph, keeps its nodes where they were and places
+    /// new ones next to their neighbours before a short force simulation
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &["initial", "projection", "pack-components"]
+    )]
+    pub previous: Option<PathBuf>,
+    /// Strength of the force pulling nodes back to their previous position
+    #[clap(long, default_value_t = ANCHOR_STRENGTH, requires = "previous")]
+    pub previous_anchor: f32,
+    /// Number of steps of the simulation after `--previous`, replaces `--steps`
+    #[clap(long, default_value_t = 500, requires = "previous")]
+    pub incremental_steps: usize,
+    /// Number of pivots, switches stress majorization to its sparse variant. Graphs of more than
+    /// 2000 nodes use 200 pivots without it
+    #[clap(long)]
+    pub pivots: Option<usize>,
     /// Scaling factor of the springs
     #[clap(short, long, default_value_t = SPING_SCALE)]
     pub spring: f32,
@@ -24,10 +311,93 @@ pub struct Args {
     /// Time delta in each computation step
     #[clap(short, long, default_value_t = TIME_DELTA)]
     pub time: f32, 
-    #[clap(short, long, default_value_t = 20000)]
+    /// Scaling factor of the pull towards the centre of mass
+    #[clap(short, long, default_value_t = GRAVITY_SCALE)]
+    pub gravity: f32,
+    /// Scaling factor of the push between nodes whose circles overlap
+    #[clap(long, default_value_t = COLLISION_SCALE)]
+    pub collision: f32,
+    /// Detect communities, export them as the `community` attribute and colour the nodes by them
+    #[clap(long, value_enum)]
+    pub communities: Option<CommunityMethod>,
+    /// Scaling factor of the pull between nodes of the same community
+    #[clap(long, default_value_t = COMMUNITY_SCALE, requires = "communities")]
+    pub community_attraction: f32,
+    /// Centralities exported as extra columns of the JSON, CSV and HTML output
+    #[clap(long, value_enum, value_delimiter = ',')]
+    pub centrality: Vec<CentralityKind>,
+    /// Centrality the radius of the nodes grows with instead of their weight
+    #[clap(long, value_enum)]
+    pub radius_by: Option<CentralityKind>,
+    /// Print the shortest path between two node ids and highlight it in the svg, html and json
+    /// output
+    #[clap(long, number_of_values = 2, value_names = &["FROM", "TO"])]
+    pub highlight_path: Option<Vec<usize>>,
+    /// Simulate every connected component on its own and pack them next to each other. Needs the
+    /// force algorithm, the components are not traced or reported
+    #[clap(long, conflicts_with_all = &["report-every", "trace"])]
+    pub pack_components: bool,
+    /// Arrangement of the components packed by `--pack-components`
+    #[clap(long, value_enum, default_value_t = ComponentPacking::Polyomino)]
+    pub packing: ComponentPacking,
+    /// Derive spring, coloumb and time parameters from the graph instead
+    #[clap(long)]
+    pub auto_tune: bool,
+    /// Refine the derived parameters with trial runs of this many steps
+    #[clap(long, requires = "auto-tune")]
+    pub tune_steps: Option<usize>,
+    /// Metric minimized by the trial runs
+    #[clap(long, value_enum, default_value_t = TuneObjective::Stress)]
+    pub tune_objective: TuneObjective,
+    /// Number of layout iterations. Has no short flag, `-s` is taken by `--spring`
+    #[clap(long, default_value_t = 20000)]
     pub steps: usize,
+    /// Move overlapping nodes apart once the layout is done
+    #[clap(long)]
+    pub remove_overlaps: bool,
+    /// Font size of the labels in layout units, lets `--remove-overlaps` separate labels too
+    #[clap(long)]
+    pub label_size: Option<f32>,
+    /// Draw the labels of the nodes into the SVG, placing the most important ones first
+    #[clap(long, value_enum)]
+    pub labels: Option<LabelPriority>,
+    /// Font size of the drawn labels in pixels
+    #[clap(long, default_value_t = 12.0, requires = "labels")]
+    pub font_size: f32,
+    /// Set labels without room next to their node further away instead of dropping them
+    #[clap(long, requires = "labels")]
+    pub leader_lines: bool,
+    /// Print energy and displacement every N steps
+    #[clap(long)]
+    pub report_every: Option<usize>,
+    /// Location of a csv file receiving energy and displacement every `--report-every` steps,
+    /// every 100 steps without it
+    #[clap(long)]
+    pub trace: Option<PathBuf>,
     #[clap(short, long, default_value_t = 1000.0)]
     pub width: f32,
-    #[clap(short, long, default_value_t = 1000.0)]
+    /// Height of the image. Has no short flag, `-h` is taken by `--help`
+    #[clap(long, default_value_t = 1000.0)]
     pub height: f32, 
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::Args;
+
+    #[test]
+    fn verify_args() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn packed_components_are_not_traced() {
+        assert!(Args::try_parse_from(["gv", "--pack-components"]).is_ok());
+        for extra in [["--trace", "trace.csv"], ["--report-every", "10"]] {
+            let args = ["gv", "--pack-components", extra[0], extra[1]];
+            assert!(Args::try_parse_from(args).is_err());
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! Communities, groups of nodes connected more strongly among each other than to the rest of
+//! the graph.
+//!
+//! Relation weights are used as the strength of a connection here, unlike the path based
+//! algorithms which treat them as lengths.
+
+use std::sync::Arc;
+
+use nohash_hasher::IntMap;
+use rand::{rngs::SmallRng, seq::SliceRandom, SeedableRng};
+
+use crate::{
+    adjacency::Adjacency,
+    model::{Node, Relation},
+};
+
+/// Attribute the community of a node is exported as.
+pub const COMMUNITY: &str = "community";
+
+/// Upper bound of the passes over all nodes in one level of Louvain and in label propagation.
+const MAX_ROUNDS: usize = 100;
+
+/// Algorithm used to find the communities.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Method {
+    /// Modularity optimization by Blondel et al., moves nodes between communities and then merges
+    /// every community into one node until the modularity stops growing.
+    Louvain,
+    /// Every node repeatedly adopts the community most of its neighbours belong to. Fast, but
+    /// finds coarser and less stable communities than Louvain.
+    LabelPropagation,
+}
+
+/// Neighbour lists with the absolute relation weights, a self loop carrying the weight inside a
+/// merged community.
+type Lists = Vec<Vec<(usize, f32)>>;
+
+fn lists(adjacency: &Adjacency) -> Lists {
+    (0..adjacency.len())
+        .map(|i| {
+            adjacency
+                .neighbours(i)
+                .iter()
+                .map(|&(j, w)| (j, w.abs()))
+                .collect()
+        })
+        .collect()
+}
+
+/// Numbers the communities by falling size, ties broken by their smallest node, so the result
+/// does not depend on the labels the algorithms happened to use.
+fn ordered(communities: &[usize]) -> Vec<usize> {
+    let mut members: IntMap<usize, (usize, usize)> = IntMap::default();
+    for (i, &c) in communities.iter().enumerate() {
+        members.entry(c).or_insert((0, i)).0 += 1;
+    }
+    let mut order: Vec<(usize, (usize, usize))> = members.into_iter().collect();
+    order.sort_by_key(|(_, (size, first))| (std::cmp::Reverse(*size), *first));
+    let ids: IntMap<usize, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(id, (c, _))| (*c, id))
+        .collect();
+    communities.iter().map(|c| ids[c]).collect()
+}
+
+/// Moves single nodes into the neighbouring community with the largest modularity gain until no
+/// move improves it. Returns the community of every node and whether any node moved.
+fn local_moving(lists: &Lists) -> (Vec<usize>, bool) {
+    let n = lists.len();
+    let degree: Vec<f32> = lists
+        .iter()
+        .map(|e| e.iter().map(|(_, w)| w).sum())
+        .collect();
+    let total: f32 = degree.iter().sum();
+    let mut community: Vec<usize> = (0..n).collect();
+    if total <= 0.0 {
+        return (community, false);
+    }
+    // Summed degree of the members of every community.
+    let mut inside = degree.clone();
+    let mut links = vec![0.0f32; n];
+    let mut touched = Vec::new();
+    let mut moved_any = false;
+    for _ in 0..MAX_ROUNDS {
+        let mut moved = false;
+        for i in 0..n {
+            let current = community[i];
+            touched.push(current);
+            for &(j, w) in &lists[i] {
+                if j != i {
+                    if links[community[j]] == 0.0 {
+                        touched.push(community[j]);
+                    }
+                    links[community[j]] += w;
+                }
+            }
+            inside[current] -= degree[i];
+            let gain = |c: usize| links[c] - inside[c] * degree[i] / total;
+            let mut best = current;
+            for &c in &touched {
+                if gain(c) > gain(best) {
+                    best = c;
+                }
+            }
+            inside[best] += degree[i];
+            community[i] = best;
+            moved |= best != current;
+            for c in touched.drain(..) {
+                links[c] = 0.0;
+            }
+        }
+        moved_any |= moved;
+        if !moved {
+            break;
+        }
+    }
+    (community, moved_any)
+}
+
+/// Merges every community into one node, returning the new lists and the community ids
+/// renumbered from zero.
+fn aggregate(lists: &Lists, community: &[usize]) -> (Lists, Vec<usize>) {
+    let mut ids: IntMap<usize, usize> = IntMap::default();
+    let renumbered: Vec<usize> = community
+        .iter()
+        .map(|c| {
+            let next = ids.len();
+            *ids.entry(*c).or_insert(next)
+        })
+        .collect();
+    let mut merged: Vec<IntMap<usize, f32>> = vec![IntMap::default(); ids.len()];
+    for (i, list) in lists.iter().enumerate() {
+        for &(j, w) in list {
+            *merged[renumbered[i]].entry(renumbered[j]).or_insert(0.0) += w;
+        }
+    }
+    let lists = merged
+        .into_iter()
+        .map(|e| {
+            let mut list: Vec<(usize, f32)> = e.into_iter().collect();
+            list.sort_unstable_by_key(|(j, _)| *j);
+            list
+        })
+        .collect();
+    (lists, renumbered)
+}
+
+pub fn louvain(adjacency: &Adjacency) -> Vec<usize> {
+    let mut lists = lists(adjacency);
+    let mut membership: Vec<usize> = (0..adjacency.len()).collect();
+    loop {
+        let (community, moved) = local_moving(&lists);
+        if !moved {
+            break;
+        }
+        let (merged, renumbered) = aggregate(&lists, &community);
+        for e in membership.iter_mut() {
+            *e = renumbered[*e];
+        }
+        lists = merged;
+    }
+    ordered(&membership)
+}
+
+pub fn label_propagation(adjacency: &Adjacency) -> Vec<usize> {
+    let lists = lists(adjacency);
+    let mut labels: Vec<usize> = (0..lists.len()).collect();
+    let mut order: Vec<usize> = (0..lists.len()).collect();
+    let mut rng = SmallRng::from_seed([0u8; 32]);
+    let mut weights: IntMap<usize, f32> = IntMap::default();
+    for _ in 0..MAX_ROUNDS {
+        order.shuffle(&mut rng);
+        let mut changed = false;
+        for &i in &order {
+            weights.clear();
+            for &(j, w) in &lists[i] {
+                if j != i {
+                    *weights.entry(labels[j]).or_insert(0.0) += w;
+                }
+            }
+            let Some(strongest) = weights.values().copied().reduce(f32::max) else {
+                continue;
+            };
+            // Keeping the current label on ties lets the propagation settle.
+            if weights.get(&labels[i]) == Some(&strongest) {
+                continue;
+            }
+            let best = weights
+                .iter()
+                .filter(|(_, w)| **w == strongest)
+                .map(|(label, _)| *label)
+                .min()
+                .unwrap();
+            labels[i] = best;
+            changed = true;
+        }
+        if !changed {
+            break;
+        }
+    }
+    ordered(&labels)
+}
+
+pub fn detect(adjacency: &Adjacency, method: Method) -> Vec<usize> {
+    match method {
+        Method::Louvain => louvain(adjacency),
+        Method::LabelPropagation => label_propagation(adjacency),
+    }
+}
+
+/// Newman's modularity of the partition, between -0.5 and 1.0. Higher values mean more weight
+/// inside the communities than expected by chance.
+pub fn modularity(adjacency: &Adjacency, communities: &[usize]) -> f32 {
+    let count = communities.iter().max().map_or(0, |e| e + 1);
+    let mut inside = vec![0.0f32; count];
+    let mut degree = vec![0.0f32; count];
+    let mut total = 0.0;
+    for (i, &c) in communities.iter().enumerate() {
+        for &(j, w) in adjacency.neighbours(i) {
+            let w = w.abs();
+            total += w;
+            degree[c] += w;
+            if communities[j] == c {
+                inside[c] += w;
+            }
+        }
+    }
+    if total <= 0.0 {
+        return 0.0;
+    }
+    inside
+        .iter()
+        .zip(&degree)
+        .map(|(inside, degree)| inside / total - (degree / total).powi(2))
+        .sum()
+}
+
+/// Detects the communities and stores them in the nodes. Returns their number and modularity.
+pub fn assign(nodes: &[Arc<Node>], relations: &[Arc<Relation>], method: Method) -> (usize, f32) {
+    let adjacency = Adjacency::from_graph(nodes, relations);
+    let communities = detect(&adjacency, method);
+    for (node, &community) in nodes.iter().zip(&communities) {
+        node.set_community(Some(community));
+    }
+    let count = communities.iter().max().map_or(0, |e| e + 1);
+    (count, modularity(&adjacency, &communities))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{adjacency::Adjacency, builder::GraphBuilder, export::Layout};
+
+    use super::{assign, detect, modularity, Method, COMMUNITY};
+
+    /// Two cliques of four nodes joined by one weak relation.
+    fn barbell() -> Adjacency {
+        let mut edges = Vec::new();
+        for offset in [0, 4] {
+            for a in 0..4 {
+                for b in (a + 1)..4 {
+                    edges.push((offset + a, offset + b, 1.0));
+                }
+            }
+        }
+        edges.push((3, 4, 0.1));
+        Adjacency::from_edges(8, edges)
+    }
+
+    #[test]
+    fn finds_both_cliques() {
+        let adjacency = barbell();
+        for method in [Method::Louvain, Method::LabelPropagation] {
+            let communities = detect(&adjacency, method);
+            assert_eq!(communities, vec![0, 0, 0, 0, 1, 1, 1, 1], "{method:?}");
+            assert!(modularity(&adjacency, &communities) > 0.45);
+        }
+        let single = vec![0; 8];
+        assert!(modularity(&barbell(), &single).abs() < 1.0E-6);
+    }
+
+    #[test]
+    fn heavy_relations_bind_stronger() {
+        // A path cut at its weakest relation.
+        let adjacency = Adjacency::from_edges(4, [(0, 1, 5.0), (1, 2, 0.5), (2, 3, 5.0)]);
+        assert_eq!(detect(&adjacency, Method::Louvain), vec![0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn exports_community_attribute() {
+        let mut builder = GraphBuilder::new();
+        for i in 0..3 {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        builder.add_edge(0, 1, 1.0).unwrap();
+        let graph = builder.build();
+        let (count, _) = assign(&graph.nodes, &graph.relations, Method::Louvain);
+        assert_eq!(count, 2);
+
+        let layout = Layout::from_graph(&graph.nodes, &graph.relations);
+        let community = |i: usize| layout.nodes[i].attributes.get(COMMUNITY).cloned();
+        assert_eq!(community(0), Some("0".to_string()));
+        assert_eq!(community(1), Some("0".to_string()));
+        assert_eq!(community(2), Some("1".to_string()));
+    }
+}
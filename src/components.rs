@@ -0,0 +1,348 @@
+//! Connected components and packing them next to each other.
+
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use nohash_hasher::IntMap;
+
+use crate::{
+    adjacency::Adjacency,
+    layout::{read_positions, write_positions},
+    model::{Graph, Node, Relation},
+    sim::{LayoutParams, SimulationState, AVAILABLE_PARALLELISM},
+};
+
+/// Average number of grid cells a component covers in [`Packing::Polyomino`].
+const CELLS_PER_COMPONENT: f32 = 100.0;
+
+/// Components with at least this many nodes are simulated by all worker threads in
+/// [`simulate_separately`], smaller ones by a single worker each.
+const PARALLEL_COMPONENT: usize = 1000;
+
+/// Arrangement of the components produced by [`pack`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Packing {
+    /// Bounding boxes on shelves, the tallest first.
+    Shelf,
+    /// Shapes rasterized on a grid and placed as close to the centre as they fit, following
+    /// Freivalds, Dogrusoz and Kikusts. Small components fill the holes of large ones.
+    Polyomino,
+}
+
+/// Connected components as lists of node indices, the largest first.
+pub fn connected_components(adjacency: &Adjacency) -> Vec<Vec<usize>> {
+    let mut seen = vec![false; adjacency.len()];
+    let mut components = Vec::new();
+    for start in 0..adjacency.len() {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut component = vec![start];
+        let mut next = 0;
+        while let Some(&node) = component.get(next) {
+            next += 1;
+            for &(neighbour, _) in adjacency.neighbours(node) {
+                if !seen[neighbour] {
+                    seen[neighbour] = true;
+                    component.push(neighbour);
+                }
+            }
+        }
+        component.sort_unstable();
+        components.push(component);
+    }
+    components.sort_by_key(|e| std::cmp::Reverse(e.len()));
+    components
+}
+
+/// Splits the graph into one graph per connected component, the largest first.
+///
+/// The parts share their nodes and relations with `graph`, so laying out a part moves the nodes
+/// of the whole graph.
+pub fn split(graph: &Graph) -> Vec<Graph> {
+    let adjacency = Adjacency::from_graph(&graph.nodes, &graph.relations);
+    let components = connected_components(&adjacency);
+    let mut part_of: IntMap<usize, usize> = IntMap::default();
+    let mut parts: Vec<Graph> = components
+        .iter()
+        .enumerate()
+        .map(|(part, members)| {
+            let nodes: Vec<Arc<Node>> = members
+                .iter()
+                .map(|&i| {
+                    part_of.insert(graph.nodes[i].id(), part);
+                    Arc::clone(&graph.nodes[i])
+                })
+                .collect();
+            Graph::new(nodes, Vec::new())
+        })
+        .collect();
+    for relation in &graph.relations {
+        if let Some(&part) = part_of.get(&relation.from.id()) {
+            parts[part].relations.push(Arc::clone(relation));
+        }
+    }
+    parts
+}
+
+/// Bounding box of the circles of `nodes` as `(min_x, min_y, max_x, max_y)`.
+fn bounding_box(nodes: &[Arc<Node>]) -> (f32, f32, f32, f32) {
+    nodes.iter().zip(read_positions(nodes)).fold(
+        (
+            f32::INFINITY,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NEG_INFINITY,
+        ),
+        |acc, (node, (x, y))| {
+            let r = node.radius();
+            (
+                acc.0.min(x - r),
+                acc.1.min(y - r),
+                acc.2.max(x + r),
+                acc.3.max(y + r),
+            )
+        },
+    )
+}
+
+/// Offsets placing boxes of the given sizes on shelves, the tallest first, so that the result
+/// is roughly square.
+fn shelf_offsets(sizes: &[(f32, f32)], gap: f32) -> Vec<(f32, f32)> {
+    let area: f32 = sizes.iter().map(|(w, h)| (w + gap) * (h + gap)).sum();
+    let widest = sizes.iter().map(|(w, _)| *w).fold(0.0, f32::max);
+    let row_width = area.sqrt().max(widest);
+
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| sizes[*b].1.total_cmp(&sizes[*a].1));
+    let mut offsets = vec![(0.0, 0.0); sizes.len()];
+    let (mut x, mut y, mut shelf_height) = (0.0, 0.0, 0.0f32);
+    for i in order {
+        let (w, h) = sizes[i];
+        if x > 0.0 && x + w > row_width {
+            x = 0.0;
+            y += shelf_height + gap;
+            shelf_height = 0.0;
+        }
+        offsets[i] = (x, y);
+        x += w + gap;
+        shelf_height = shelf_height.max(h);
+    }
+    offsets
+}
+
+/// Cells of the grid covered by the circles and relations of a part, grown by one cell so
+/// neighbouring shapes keep a gap.
+fn polyomino(nodes: &[Arc<Node>], relations: &[Arc<Relation>], cell: f32) -> Vec<(i32, i32)> {
+    let to_cell = |v: f32| (v / cell).floor() as i32;
+    let mut cells = HashSet::new();
+    let mut cover = |x0: f32, y0: f32, x1: f32, y1: f32| {
+        for cx in to_cell(x0) - 1..=to_cell(x1) + 1 {
+            for cy in to_cell(y0) - 1..=to_cell(y1) + 1 {
+                cells.insert((cx, cy));
+            }
+        }
+    };
+    for node in nodes {
+        let loc = *node.loc.read().unwrap();
+        let r = node.radius();
+        cover(loc.x - r, loc.y - r, loc.x + r, loc.y + r);
+    }
+    for relation in relations {
+        let from = *relation.from.loc.read().unwrap();
+        let to = *relation.to.loc.read().unwrap();
+        let samples = ((from.to(to).length() / cell).ceil() as usize).max(1);
+        for i in 0..=samples {
+            let t = i as f32 / samples as f32;
+            let (x, y) = (from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t);
+            cover(x, y, x, y);
+        }
+    }
+    let mut cells: Vec<(i32, i32)> = cells.into_iter().collect();
+    cells.sort_unstable();
+    cells
+}
+
+/// Square rings of grid offsets around the origin, the innermost ring first.
+fn spiral() -> impl Iterator<Item = (i32, i32)> {
+    (0..).flat_map(|r: i32| {
+        (-r..=r).flat_map(move |dx| {
+            (-r..=r)
+                .filter(move |dy| dx.abs() == r || dy.abs() == r)
+                .map(move |dy| (dx, dy))
+        })
+    })
+}
+
+/// Offsets placing the polyominoes of the parts, the largest first, on the free spot closest to
+/// the centre of the grid.
+fn polyomino_offsets(parts: &[Graph], boxes: &[(f32, f32, f32, f32)]) -> Vec<(f32, f32)> {
+    let area: f32 = boxes
+        .iter()
+        .map(|(min_x, min_y, max_x, max_y)| (max_x - min_x) * (max_y - min_y))
+        .sum();
+    let cell = (area / (CELLS_PER_COMPONENT * parts.len() as f32)).sqrt();
+    let cell = if cell.is_normal() { cell } else { 1.0 };
+
+    let shapes: Vec<Vec<(i32, i32)>> = parts
+        .iter()
+        .map(|e| polyomino(&e.nodes, &e.relations, cell))
+        .collect();
+    let mut order: Vec<usize> = (0..parts.len()).collect();
+    order.sort_by_key(|e| std::cmp::Reverse(shapes[*e].len()));
+
+    let mut occupied: HashSet<(i32, i32)> = HashSet::new();
+    let mut offsets = vec![(0.0, 0.0); parts.len()];
+    for i in order {
+        // Shapes are moved relative to their middle cell, so small offsets stay near the centre.
+        let (min_x, min_y, max_x, max_y) = boxes[i];
+        let middle = (
+            ((min_x + max_x) / 2.0 / cell).floor() as i32,
+            ((min_y + max_y) / 2.0 / cell).floor() as i32,
+        );
+        let shape: Vec<(i32, i32)> = shapes[i]
+            .iter()
+            .map(|(x, y)| (x - middle.0, y - middle.1))
+            .collect();
+        let (dx, dy) = spiral()
+            .find(|(dx, dy)| {
+                shape
+                    .iter()
+                    .all(|(x, y)| !occupied.contains(&(x + dx, y + dy)))
+            })
+            .unwrap();
+        occupied.extend(shape.iter().map(|(x, y)| (x + dx, y + dy)));
+        offsets[i] = ((dx - middle.0) as f32 * cell, (dy - middle.1) as f32 * cell);
+    }
+    offsets
+}
+
+/// Moves the parts next to each other without overlapping.
+///
+/// With [`Packing::Shelf`] the gap between two parts is a twentieth of the side of a square
+/// holding all of them, with [`Packing::Polyomino`] it is at least one grid cell.
+pub fn pack(parts: &[Graph], packing: Packing) {
+    let boxes: Vec<(f32, f32, f32, f32)> = parts.iter().map(|e| bounding_box(&e.nodes)).collect();
+    let offsets: Vec<(f32, f32)> = match packing {
+        Packing::Shelf => {
+            let sizes: Vec<(f32, f32)> = boxes
+                .iter()
+                .map(|(min_x, min_y, max_x, max_y)| (max_x - min_x, max_y - min_y))
+                .collect();
+            let gap = sizes.iter().map(|(w, h)| w * h).sum::<f32>().sqrt() / 20.0;
+            shelf_offsets(&sizes, gap)
+                .into_iter()
+                .zip(&boxes)
+                .map(|((x, y), (min_x, min_y, _, _))| (x - min_x, y - min_y))
+                .collect()
+        }
+        Packing::Polyomino => polyomino_offsets(parts, &boxes),
+    };
+    for (part, (x, y)) in parts.iter().zip(offsets) {
+        let moved: Vec<(f32, f32)> = read_positions(&part.nodes)
+            .into_iter()
+            .map(|(px, py)| (px + x, py + y))
+            .collect();
+        write_positions(&part.nodes, &moved);
+    }
+}
+
+/// Simulates every connected component on its own for `steps` steps and packs the results.
+///
+/// Components never feel the repulsion of other components, which saves the quadratic cost
+/// between them. Large components are simulated one after another by all worker threads, the
+/// small ones are spread over the workers and simulated side by side. Returns the summed
+/// displacement of the last steps over all components.
+pub fn simulate_separately(
+    graph: &Graph,
+    params: LayoutParams,
+    steps: usize,
+    packing: Packing,
+) -> std::io::Result<f32> {
+    let parts = split(graph);
+    // Parts are sorted by size, so the large ones come first.
+    let large = parts
+        .iter()
+        .take_while(|e| e.nodes.len() >= PARALLEL_COMPONENT)
+        .count();
+    let mut change = 0.0;
+    for part in &parts[..large] {
+        change += SimulationState::from_graph(part.clone(), params).run_n_steps(steps)?;
+    }
+
+    let small = &parts[large..];
+    let next = AtomicUsize::new(0);
+    change += crossbeam::scope(|scope| {
+        let handles: Vec<_> = (0..(*AVAILABLE_PARALLELISM).min(small.len()))
+            .map(|_| {
+                scope.spawn(|_| {
+                    let mut change = 0.0;
+                    while let Some(part) = small.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        change += SimulationState::from_graph(part.clone(), params)
+                            .run_n_steps_serial(steps);
+                    }
+                    change
+                })
+            })
+            .collect();
+        handles.into_iter().map(|e| e.join().unwrap()).sum::<f32>()
+    })
+    .unwrap();
+
+    pack(&parts, packing);
+    Ok(change)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{builder::GraphBuilder, layout::read_positions, sim::LayoutParams};
+
+    use super::{simulate_separately, split, Packing};
+
+    #[test]
+    fn packs_components_apart() {
+        let mut builder = GraphBuilder::new();
+        for i in 0..7 {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        for (from, to) in [(0, 1), (1, 2), (2, 0), (3, 4), (5, 6)] {
+            builder.add_edge(from, to, 1.0).unwrap();
+        }
+        let graph = builder.build();
+        let parts = split(&graph);
+        let sizes: Vec<(usize, usize)> = parts
+            .iter()
+            .map(|e| (e.nodes.len(), e.relations.len()))
+            .collect();
+        assert_eq!(sizes, vec![(3, 3), (2, 1), (2, 1)]);
+
+        for packing in [Packing::Shelf, Packing::Polyomino] {
+            simulate_separately(&graph, LayoutParams::default(), 200, packing).unwrap();
+            let positions = read_positions(&graph.nodes);
+            assert!(positions
+                .iter()
+                .all(|(x, y)| x.is_finite() && y.is_finite()));
+            // Circles of different components never overlap.
+            let component = [0, 0, 0, 1, 1, 2, 2];
+            for a in 0..7 {
+                for b in (a + 1)..7 {
+                    if component[a] == component[b] {
+                        continue;
+                    }
+                    let (p, q) = (positions[a], positions[b]);
+                    let distance = (p.0 - q.0).hypot(p.1 - q.1);
+                    assert!(
+                        distance >= 2.0,
+                        "Nodes {a} and {b} overlap with {packing:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    sync::Arc,
+};
+
+use nohash_hasher::IntMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    community::COMMUNITY,
+    model::{Node, Relation},
+};
+
+/// Plain, serializable copy of a graph together with its node positions.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Layout {
+    pub nodes: Vec<LayoutNode>,
+    pub edges: Vec<LayoutEdge>,
+    /// Ids of the nodes of a highlighted path, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LayoutNode {
+    pub id: usize,
+    #[serde(default)]
+    pub label: Option<String>,
+    pub x: f32,
+    pub y: f32,
+    pub weight: f32,
+    /// Radius set apart from the weight, see [`Node::radius`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f32>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl LayoutNode {
+    /// Radius the node is drawn with, the absolute weight unless set otherwise.
+    pub fn drawn_radius(&self) -> f32 {
+        self.radius.unwrap_or(self.weight.abs())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LayoutEdge {
+    pub from: usize,
+    pub to: usize,
+    pub weight: f32,
+    /// Points the edge bends at on its way from `from` to `to`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bends: Vec<(f32, f32)>,
+}
+
+impl Layout {
+    pub fn from_graph(nodes: &[Arc<Node>], relations: &[Arc<Relation>]) -> Self {
+        Self {
+            nodes: nodes.iter().map(|e| LayoutNode::from(e.as_ref())).collect(),
+            edges: relations
+                .iter()
+                .map(|e| LayoutEdge::from(e.as_ref()))
+                .collect(),
+            path: Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    /// Nodes as CSV rows of id, label, position and weight followed by one column per attribute.
+    pub fn to_csv(&self) -> std::io::Result<String> {
+        let columns: BTreeSet<&str> = self
+            .nodes
+            .iter()
+            .flat_map(|e| e.attributes.keys().map(String::as_str))
+            .collect();
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        let header = ["id", "label", "x", "y", "weight"];
+        writer.write_record(header.iter().copied().chain(columns.iter().copied()))?;
+        for node in &self.nodes {
+            let fixed = [
+                node.id.to_string(),
+                node.label.clone().unwrap_or_default(),
+                node.x.to_string(),
+                node.y.to_string(),
+                node.weight.to_string(),
+            ];
+            let attributes = columns
+                .iter()
+                .map(|e| node.attributes.get(*e).cloned().unwrap_or_default());
+            writer.write_record(fixed.into_iter().chain(attributes))?;
+        }
+        let bytes = writer
+            .into_inner()
+            .map_err(|e| std::io::Error::new(e.error().kind(), e.error().to_string()))?;
+        String::from_utf8(bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn from_json<R: Read>(reader: R) -> serde_json::Result<Self> {
+        serde_json::from_reader(reader)
+    }
+
+    /// Maps node ids to their position in [`Layout::nodes`].
+    pub fn index(&self) -> IntMap<usize, usize> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.id, i))
+            .collect()
+    }
+}
+
+impl From<&Node> for LayoutNode {
+    fn from(n: &Node) -> Self {
+        let loc = *n.loc.read().unwrap();
+        let mut attributes = n.attributes.clone();
+        if let Some(community) = n.community() {
+            attributes.insert(COMMUNITY.to_string(), community.to_string());
+        }
+        Self {
+            id: n.id(),
+            label: n.label.clone(),
+            x: loc.x,
+            y: loc.y,
+            weight: n.weight,
+            radius: n.radius_override(),
+            attributes,
+        }
+    }
+}
+
+impl From<&Relation> for LayoutEdge {
+    fn from(r: &Relation) -> Self {
+        Self {
+            from: r.from.id(),
+            to: r.to.id(),
+            weight: r.weight,
+            bends: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::GraphBuilder;
+
+    use super::Layout;
+
+    #[test]
+    fn writes_attributes_as_columns() {
+        let mut builder = GraphBuilder::new();
+        builder
+            .add_node_at(1, 1.0, 2.0, 3.0)
+            .unwrap()
+            .label(1, "Berlin, Hbf")
+            .unwrap()
+            .attribute(1, "lat", "52.52")
+            .unwrap()
+            .add_node_at(2, 0.0, 0.0, 1.0)
+            .unwrap();
+        let graph = builder.build();
+        let mut layout = Layout::from_graph(&graph.nodes, &graph.relations);
+        layout.nodes[1]
+            .attributes
+            .insert("degree".to_string(), "0".to_string());
+        let csv = layout.to_csv().unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "id,label,x,y,weight,degree,lat",
+                "1,\"Berlin, Hbf\",1,2,3,,52.52",
+                "2,,0,0,1,0,",
+            ]
+        );
+    }
+
+    #[test]
+    fn exports_radius_set_apart_from_weight() {
+        let mut builder = GraphBuilder::new();
+        builder.add_node(1, 2.0).unwrap().add_node(2, 2.0).unwrap();
+        let graph = builder.build();
+        graph.nodes[0].set_radius(Some(5.0));
+        let layout = Layout::from_graph(&graph.nodes, &graph.relations);
+        let json = layout.to_json().unwrap();
+        assert_eq!(json.matches(r#""radius":5.0"#).count(), 1);
+        assert_eq!(json.matches("radius").count(), 1);
+        assert_eq!(layout.nodes[1].drawn_radius(), 2.0);
+
+        let rebuilt = GraphBuilder::from_layout(&Layout::from_json(json.as_bytes()).unwrap())
+            .unwrap()
+            .build();
+        assert_eq!(rebuilt.nodes[0].radius(), 5.0);
+        assert_eq!(rebuilt.nodes[1].radius_override(), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! Reduction of a loaded graph to the part worth laying out.
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    adjacency::Adjacency,
+    builder::{GraphBuilder, GraphError},
+    components::connected_components,
+    export::Layout,
+    model::{Graph, Node},
+};
+
+/// Comparison of a node attribute with a value, written as `key=value`, `key!=value`,
+/// `key<number`, `key<=number`, `key>number` or `key>=number`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Predicate {
+    pub key: String,
+    pub comparison: Comparison,
+    pub value: String,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+impl Predicate {
+    /// Nodes without the attribute only match `!=`, numeric comparisons only match numbers.
+    pub fn matches(&self, node: &Node) -> bool {
+        let Some(actual) = node.attributes.get(&self.key) else {
+            return self.comparison == Comparison::NotEqual;
+        };
+        let number = || {
+            Some((
+                actual.trim().parse::<f32>().ok()?,
+                self.value.trim().parse::<f32>().ok()?,
+            ))
+        };
+        match self.comparison {
+            Comparison::Equal => *actual == self.value,
+            Comparison::NotEqual => *actual != self.value,
+            Comparison::Less => number().is_some_and(|(a, b)| a < b),
+            Comparison::LessOrEqual => number().is_some_and(|(a, b)| a <= b),
+            Comparison::Greater => number().is_some_and(|(a, b)| a > b),
+            Comparison::GreaterOrEqual => number().is_some_and(|(a, b)| a >= b),
+        }
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Two character operators first, so `<=` is not read as `<` followed by `=value`.
+        let comparisons = [
+            Comparison::NotEqual,
+            Comparison::LessOrEqual,
+            Comparison::GreaterOrEqual,
+            Comparison::Equal,
+            Comparison::Less,
+            Comparison::Greater,
+        ];
+        let (at, comparison) = comparisons
+            .iter()
+            .filter_map(|e| s.find(e.symbol()).map(|at| (at, *e)))
+            .min_by_key(|(at, e)| (*at, std::cmp::Reverse(e.symbol().len())))
+            .ok_or_else(|| format!("{s:?} has no comparison like key=value or key>number"))?;
+        let key = s[..at].trim();
+        if key.is_empty() {
+            return Err(format!("{s:?} has no attribute name"));
+        }
+        Ok(Self {
+            key: key.to_string(),
+            comparison,
+            value: s[at + comparison.symbol().len()..].trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.key, self.comparison.symbol(), self.value)
+    }
+}
+
+/// Filters applied one after the other in the order of the fields.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    /// Nodes have to match all predicates.
+    pub predicates: Vec<Predicate>,
+    /// Relations lighter than this are removed.
+    pub min_weight: Option<f32>,
+    /// Only the node with this id and the nodes at most the given number of hops away are kept.
+    pub ego: Option<(usize, usize)>,
+    /// Nodes with fewer relations are removed, repeatedly until all remaining nodes have as many.
+    pub min_degree: Option<usize>,
+    /// Only the connected component with the most nodes is kept.
+    pub largest_component: bool,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns a new graph of the remaining nodes and the relations between them.
+    ///
+    /// The remaining nodes keep their ids, positions, labels and attributes but are indexed
+    /// from zero again, so the result can be used like a graph read from files.
+    pub fn apply(&self, graph: &Graph) -> Result<Graph, GraphError> {
+        let layout = Layout::from_graph(&graph.nodes, &graph.relations);
+        let index = layout.index();
+        let n = graph.nodes.len();
+        let mut nodes: Vec<bool> = graph
+            .nodes
+            .iter()
+            .map(|node| self.predicates.iter().all(|e| e.matches(node)))
+            .collect();
+        // Relations as index pairs, `None` once removed.
+        let mut edges: Vec<Option<(usize, usize)>> = layout
+            .edges
+            .iter()
+            .map(|e| {
+                let pair = (index[&e.from], index[&e.to]);
+                let heavy = self.min_weight.is_none_or(|min| e.weight >= min);
+                heavy.then_some(pair)
+            })
+            .collect();
+        let adjacency = |nodes: &[bool], edges: &[Option<(usize, usize)>]| {
+            Adjacency::from_edges(
+                n,
+                edges
+                    .iter()
+                    .flatten()
+                    .filter(|(a, b)| nodes[*a] && nodes[*b])
+                    .map(|&(a, b)| (a, b, 1.0)),
+            )
+        };
+
+        if let Some((id, hops)) = self.ego {
+            let centre = *index.get(&id).ok_or(GraphError::UnknownNode(id))?;
+            if !nodes[centre] {
+                return Err(GraphError::UnknownNode(id));
+            }
+            let distances = adjacency(&nodes, &edges).hops(centre);
+            for (keep, distance) in nodes.iter_mut().zip(distances) {
+                *keep &= distance.is_some_and(|e| e <= hops);
+            }
+        }
+        if let Some(min) = self.min_degree {
+            loop {
+                let current = adjacency(&nodes, &edges);
+                let removed: Vec<usize> = (0..n)
+                    .filter(|e| nodes[*e] && current.degree(*e) < min)
+                    .collect();
+                if removed.is_empty() {
+                    break;
+                }
+                removed.into_iter().for_each(|e| nodes[e] = false);
+            }
+        }
+        if self.largest_component {
+            let components = connected_components(&adjacency(&nodes, &edges));
+            // Removed nodes form components of their own, the largest with kept nodes wins.
+            let largest = components
+                .into_iter()
+                .find(|e| nodes[e[0]])
+                .unwrap_or_default();
+            let mut kept = vec![false; n];
+            largest.into_iter().for_each(|e| kept[e] = true);
+            nodes = kept;
+        }
+
+        for edge in edges.iter_mut() {
+            if let Some((a, b)) = *edge {
+                if !(nodes[a] && nodes[b]) {
+                    *edge = None;
+                }
+            }
+        }
+        let remaining = Layout {
+            nodes: layout
+                .nodes
+                .iter()
+                .zip(&nodes)
+                .filter(|(_, keep)| **keep)
+                .map(|(e, _)| e.clone())
+                .collect(),
+            edges: layout
+                .edges
+                .iter()
+                .zip(&edges)
+                .filter(|(_, edge)| edge.is_some())
+                .map(|(e, _)| e.clone())
+                .collect(),
+            path: Vec::new(),
+        };
+        Ok(GraphBuilder::from_layout(&remaining)?.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::GraphBuilder,
+        model::{Graph, Node},
+    };
+
+    use super::{Comparison, Filter, Predicate};
+
+    /// A path 0 - 1 - 2 - 3 - 4 with a triangle 1 - 5 - 6 and an isolated node 7.
+    fn graph() -> Graph {
+        let mut builder = GraphBuilder::new();
+        for i in 0..8 {
+            builder
+                .add_node(i, 1.0)
+                .unwrap()
+                .attribute(i, "zone", if i < 4 { "a" } else { "b" })
+                .unwrap();
+        }
+        for (from, to, weight) in [
+            (0, 1, 1.0),
+            (1, 2, 2.0),
+            (2, 3, 1.0),
+            (3, 4, 3.0),
+            (1, 5, 1.0),
+            (5, 6, 1.0),
+            (6, 1, 1.0),
+        ] {
+            builder.add_edge(from, to, weight).unwrap();
+        }
+        builder.build()
+    }
+
+    fn ids(graph: &Graph) -> Vec<usize> {
+        graph.nodes.iter().map(|e| e.id()).collect()
+    }
+
+    #[test]
+    fn parses_predicates() {
+        let parsed: Predicate = "zone <= 4".parse().unwrap();
+        assert_eq!(parsed.key, "zone");
+        assert_eq!(parsed.comparison, Comparison::LessOrEqual);
+        assert_eq!(parsed.value, "4");
+        assert_eq!(
+            "name!=Berlin".parse::<Predicate>().unwrap().comparison,
+            Comparison::NotEqual
+        );
+        assert!("zone".parse::<Predicate>().is_err());
+        assert!("=a".parse::<Predicate>().is_err());
+    }
+
+    #[test]
+    fn extracts_ego_network() {
+        let ego = Filter {
+            ego: Some((2, 1)),
+            ..Filter::default()
+        };
+        let result = ego.apply(&graph()).unwrap();
+        assert_eq!(ids(&result), vec![1, 2, 3]);
+        assert_eq!(result.relations.len(), 2);
+        // Re-indexed nodes still know their relations.
+        let moved = result.nodes[0].calc_new_position(
+            &result.nodes,
+            Node::centre(&result.nodes),
+            &Default::default(),
+        );
+        assert!(moved.x.is_finite());
+
+        assert!(Filter {
+            ego: Some((42, 1)),
+            ..Filter::default()
+        }
+        .apply(&graph())
+        .is_err());
+    }
+
+    #[test]
+    fn combines_filters() {
+        let heavy = Filter {
+            min_weight: Some(2.0),
+            largest_component: true,
+            ..Filter::default()
+        };
+        assert_eq!(ids(&heavy.apply(&graph()).unwrap()), vec![1, 2]);
+
+        let core = Filter {
+            min_degree: Some(2),
+            ..Filter::default()
+        };
+        // Removing the leaves 0, 4 and 7 leaves 3 and then 2 with a single relation.
+        assert_eq!(ids(&core.apply(&graph()).unwrap()), vec![1, 5, 6]);
+
+        let zone = Filter {
+            predicates: vec!["zone=b".parse().unwrap()],
+            largest_component: true,
+            ..Filter::default()
+        };
+        assert_eq!(ids(&zone.apply(&graph()).unwrap()), vec![5, 6]);
+    }
+}
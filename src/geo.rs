@@ -0,0 +1,156 @@
+//! Placement of nodes by the `lat` and `lon` columns of the node file.
+
+use std::{f32::consts::PI, io, sync::Arc};
+
+use crate::{
+    builder::INITIAL_EXTENT,
+    model::{Anchor, Coordinates, Node},
+};
+
+/// Attribute holding the latitude in degrees.
+pub const LATITUDE: &str = "lat";
+/// Attribute holding the longitude in degrees.
+pub const LONGITUDE: &str = "lon";
+/// Anchor strength used when none is given.
+pub const ANCHOR_STRENGTH: f32 = 1.0;
+
+/// Web Mercator cuts off the poles at this latitude so the map stays square.
+const MERCATOR_LIMIT: f32 = 85.051_13;
+
+/// Map projection from latitude and longitude to the plane.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Projection {
+    /// Web Mercator, keeps angles and local shapes.
+    Mercator,
+    /// Plate carrée, longitude and latitude used as is.
+    Equirectangular,
+}
+
+impl Projection {
+    /// Projects degrees onto the plane with y growing southwards, as in the rendered image.
+    pub fn project(self, lat: f32, lon: f32) -> (f32, f32) {
+        let x = lon.to_radians();
+        let y = match self {
+            Projection::Mercator => {
+                let lat = lat.clamp(-MERCATOR_LIMIT, MERCATOR_LIMIT).to_radians();
+                (PI / 4.0 + lat / 2.0).tan().ln()
+            }
+            Projection::Equirectangular => lat.to_radians(),
+        };
+        (x, -y)
+    }
+}
+
+fn parse(node: &Node, column: &str) -> io::Result<Option<f32>> {
+    match node.attributes.get(column).map(|e| e.trim()) {
+        None | Some("") => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Node {} has an invalid {column} of {value:?}", node.id()),
+            )
+        }),
+    }
+}
+
+/// Geographic coordinates of a node, `None` unless it has both a latitude and a longitude.
+pub fn coordinates(node: &Node) -> io::Result<Option<(f32, f32)>> {
+    Ok(parse(node, LATITUDE)?.zip(parse(node, LONGITUDE)?))
+}
+
+/// Moves every node with geographic coordinates to its projected position and returns how many
+/// were placed.
+///
+/// The projected positions are scaled to the area random placement covers, so the simulation
+/// parameters keep working. With an `anchor` strength the placed nodes are also anchored there,
+/// which lets a simulation move them out of each other's way without losing the geography.
+pub fn place(
+    nodes: &[Arc<Node>],
+    projection: Projection,
+    anchor: Option<f32>,
+) -> io::Result<usize> {
+    let mut placed = Vec::new();
+    for node in nodes {
+        if let Some((lat, lon)) = coordinates(node)? {
+            placed.push((node, projection.project(lat, lon)));
+        }
+    }
+    let (min_x, max_x, min_y, max_y) = placed.iter().fold(
+        (
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+        ),
+        |acc, (_, (x, y))| (acc.0.min(*x), acc.1.max(*x), acc.2.min(*y), acc.3.max(*y)),
+    );
+    let extent = (max_x - min_x).max(max_y - min_y);
+    let scale = if extent > 0.0 {
+        INITIAL_EXTENT / extent
+    } else {
+        1.0
+    };
+    for (node, (x, y)) in &placed {
+        let at = Coordinates {
+            x: (x - min_x) * scale,
+            y: (y - min_y) * scale,
+        };
+        node.update_coordinates(at);
+        node.set_anchor(anchor.map(|strength| Anchor { at, strength }));
+    }
+    Ok(placed.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::GraphBuilder;
+
+    use super::{place, Projection};
+
+    #[test]
+    fn places_and_anchors_stations() {
+        let mut builder = GraphBuilder::new();
+        let stations = [
+            (1, "52.52", "13.40"),
+            (2, "48.14", "11.58"),
+            (3, "53.55", "9.99"),
+        ];
+        for (id, lat, lon) in stations {
+            builder
+                .add_node(id, 1.0)
+                .unwrap()
+                .attribute(id, "lat", lat)
+                .unwrap()
+                .attribute(id, "lon", lon)
+                .unwrap();
+        }
+        builder.add_node(4, 1.0).unwrap();
+        let graph = builder.build();
+
+        let placed = place(&graph.nodes, Projection::Mercator, Some(2.0)).unwrap();
+        assert_eq!(placed, 3);
+        let berlin = *graph.nodes[0].loc.read().unwrap();
+        let munich = *graph.nodes[1].loc.read().unwrap();
+        let hamburg = *graph.nodes[2].loc.read().unwrap();
+        // Munich is south of Berlin, Hamburg west of it.
+        assert!(munich.y > berlin.y);
+        assert!(hamburg.x < berlin.x);
+        assert!((munich.y - 100.0).abs() < 1.0E-3);
+        assert_eq!(graph.nodes[0].anchor().unwrap().strength, 2.0);
+        assert!(graph.nodes[3].anchor().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_latitude() {
+        let mut builder = GraphBuilder::new();
+        builder
+            .add_node(1, 1.0)
+            .unwrap()
+            .attribute(1, "lat", "north")
+            .unwrap()
+            .attribute(1, "lon", "13.4")
+            .unwrap();
+        let graph = builder.build();
+        assert!(place(&graph.nodes, Projection::Equirectangular, None).is_err());
+    }
+}
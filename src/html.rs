@@ -0,0 +1,60 @@
+use crate::export::Layout;
+
+const VIEWER_TEMPLATE: &str = include_str!("viewer.html");
+
+/// Renders a self-contained HTML page with an interactive viewer for the layout.
+///
+/// The layout is embedded as JSON, the viewer itself is plain JavaScript without
+/// any external dependencies.
+pub fn render(layout: &Layout, x: f32, y: f32) -> serde_json::Result<String> {
+    // Markup like `</script>` or `<!--<script>` inside the JSON would end the data block early
+    // or hide its end. These characters only occur inside JSON strings, where escapes are valid.
+    let data = layout
+        .to_json()?
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026");
+
+    Ok(VIEWER_TEMPLATE
+        .replace("{{WIDTH}}", &x.to_string())
+        .replace("{{HEIGHT}}", &y.to_string())
+        .replace("{{LAYOUT}}", &data))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{Layout, LayoutNode};
+
+    #[test]
+    fn embeds_escaped_layout() {
+        let layout = Layout {
+            nodes: ["</script><b>", "<!--<script>"]
+                .into_iter()
+                .enumerate()
+                .map(|(i, label)| LayoutNode {
+                    id: 7 + i,
+                    label: Some(label.to_string()),
+                    x: 1.0,
+                    y: 2.0,
+                    weight: 3.0,
+                    radius: None,
+                    attributes: Default::default(),
+                })
+                .collect(),
+            edges: Vec::new(),
+            path: vec![7],
+        };
+        let html = super::render(&layout, 800.0, 600.0).unwrap();
+        assert!(!html.contains("{{LAYOUT}}"));
+        assert!(html.contains(r#""id":7"#));
+        assert!(html.contains(r#""path":[7]"#));
+        assert_eq!(html.matches("</script>").count(), 2);
+        assert!(!html.contains("<!--"));
+
+        // The data block still holds the labels as they were.
+        let start = html.find(r#"type="application/json">"#).unwrap() + 24;
+        let end = start + html[start..].find("</script>").unwrap();
+        let embedded: serde_json::Value = serde_json::from_str(&html[start..end]).unwrap();
+        assert_eq!(embedded["nodes"][1]["label"], "<!--<script>");
+    }
+}
@@ -0,0 +1,226 @@
+//! Placement of a changed graph by an earlier layout of it, so a new layout run only adapts the
+//! picture to the changes instead of drawing a new one.
+
+use std::{f32::consts::PI, fmt, sync::Arc};
+
+use nohash_hasher::IntMap;
+
+use crate::{
+    adjacency::Adjacency,
+    export::Layout,
+    model::{Anchor, Coordinates, Node, Relation},
+};
+
+/// Distance of a new node from the centre of its placed neighbours, so new nodes sharing their
+/// neighbours do not start on top of each other.
+const OFFSET: f32 = 1.0;
+
+/// Angle between the offsets of consecutive new nodes, spreading them evenly around a centre.
+const GOLDEN_ANGLE: f32 = PI * 0.763_932;
+
+/// Number of nodes in each category after [`place`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Placed {
+    /// Nodes moved to their previous position.
+    pub kept: usize,
+    /// New nodes moved next to their neighbours.
+    pub added: usize,
+    /// New nodes without a path to a kept node, left where they were.
+    pub unplaced: usize,
+    /// Nodes of the previous layout missing from the graph.
+    pub removed: usize,
+}
+
+impl fmt::Display for Placed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} kept {} added {} unplaced {} removed",
+            self.kept, self.added, self.unplaced, self.removed
+        )
+    }
+}
+
+/// Moves the nodes also found in `previous` by id to their position there and places the new
+/// ones at the centre of their already placed neighbours, the ones next to kept nodes first.
+///
+/// With an `anchor` strength the kept nodes are anchored at their previous position, so a
+/// following simulation makes room for the changes without rearranging the whole picture.
+pub fn place(
+    nodes: &[Arc<Node>],
+    relations: &[Arc<Relation>],
+    previous: &Layout,
+    anchor: Option<f32>,
+) -> Placed {
+    let positions: IntMap<usize, Coordinates> = previous
+        .nodes
+        .iter()
+        .map(|e| (e.id, Coordinates { x: e.x, y: e.y }))
+        .collect();
+    let mut placed: Vec<Option<Coordinates>> = nodes
+        .iter()
+        .map(|e| positions.get(&e.id()).copied())
+        .collect();
+    let kept = placed.iter().flatten().count();
+    let ids: IntMap<usize, ()> = nodes.iter().map(|e| (e.id(), ())).collect();
+    let removed = previous
+        .nodes
+        .iter()
+        .filter(|e| !ids.contains_key(&e.id))
+        .count();
+    for (node, at) in nodes.iter().zip(&placed) {
+        if let Some(at) = *at {
+            node.update_coordinates(at);
+            node.set_anchor(anchor.map(|strength| Anchor { at, strength }));
+        }
+    }
+
+    let adjacency = Adjacency::from_graph(nodes, relations);
+    let mut added = 0;
+    loop {
+        // Nodes of one pass only see the positions of earlier passes, so the order of the nodes
+        // does not matter.
+        let pass: Vec<(usize, Coordinates)> = (0..nodes.len())
+            .filter(|i| placed[*i].is_none())
+            .filter_map(|i| {
+                let neighbours: Vec<Coordinates> = adjacency
+                    .neighbours(i)
+                    .iter()
+                    .filter_map(|(j, _)| placed[*j])
+                    .collect();
+                if neighbours.is_empty() {
+                    return None;
+                }
+                let count = neighbours.len() as f32;
+                let angle = GOLDEN_ANGLE * (added + i) as f32;
+                let at = Coordinates {
+                    x: neighbours.iter().map(|e| e.x).sum::<f32>() / count + OFFSET * angle.cos(),
+                    y: neighbours.iter().map(|e| e.y).sum::<f32>() / count + OFFSET * angle.sin(),
+                };
+                Some((i, at))
+            })
+            .collect();
+        if pass.is_empty() {
+            break;
+        }
+        added += pass.len();
+        for (i, at) in pass {
+            nodes[i].update_coordinates(at);
+            placed[i] = Some(at);
+        }
+    }
+    Placed {
+        kept,
+        added,
+        unplaced: nodes.len() - kept - added,
+        removed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::GraphBuilder,
+        export::Layout,
+        layout::read_positions,
+        sim::{LayoutParams, SimulationState},
+    };
+
+    use super::{place, Placed};
+
+    #[test]
+    fn keeps_old_nodes_and_places_new_ones_nearby() {
+        let mut builder = GraphBuilder::new();
+        for (id, x, y) in [(0, 0.0, 0.0), (1, 20.0, 0.0), (2, 10.0, 10.0)] {
+            builder.add_node_at(id, x, y, 1.0).unwrap();
+        }
+        builder.add_edge(0, 1, 1.0).unwrap();
+        builder.add_edge(1, 2, 1.0).unwrap();
+        let old = builder.build();
+        let previous = Layout::from_graph(&old.nodes, &old.relations);
+
+        // Node 2 is gone, 3 hangs between 0 and 1, 4 only at 3 and 5 is on its own.
+        let mut builder = GraphBuilder::new();
+        for id in [0, 1, 3, 4, 5] {
+            builder.add_node(id, 1.0).unwrap();
+        }
+        for (from, to) in [(0, 1), (0, 3), (3, 1), (3, 4)] {
+            builder.add_edge(from, to, 1.0).unwrap();
+        }
+        let graph = builder.build();
+        let placed = place(&graph.nodes, &graph.relations, &previous, Some(1.0));
+        assert_eq!(
+            placed,
+            Placed {
+                kept: 2,
+                added: 2,
+                unplaced: 1,
+                removed: 1
+            }
+        );
+        let positions = read_positions(&graph.nodes);
+        assert_eq!(positions[0], (0.0, 0.0));
+        let near = |(x, y): (f32, f32), (cx, cy): (f32, f32)| {
+            ((x - cx).powi(2) + (y - cy).powi(2)).sqrt() <= 1.0 + 1.0E-4
+        };
+        assert!(near(positions[2], (10.0, 0.0)));
+        assert!(near(positions[3], positions[2]));
+        assert!(graph.nodes[0].anchor().is_some());
+        assert!(graph.nodes[2].anchor().is_none());
+
+        // The anchors hold the picture together while the new nodes settle.
+        let state = SimulationState::from_graph(graph.clone(), LayoutParams::default());
+        state.run_n_steps(200).unwrap();
+        let settled = read_positions(&graph.nodes);
+        assert!(settled[0].0.abs() < 5.0 && settled[1].0 > 15.0);
+    }
+
+    #[test]
+    fn leaves_new_graph_unplaced_and_unanchored() {
+        let mut builder = GraphBuilder::new();
+        builder.add_node_at(7, 1.0, 1.0, 1.0).unwrap();
+        let old = builder.build();
+        let previous = Layout::from_graph(&old.nodes, &old.relations);
+
+        // None of the nodes was laid out before, so there is nothing to place them next to.
+        let mut builder = GraphBuilder::new();
+        for (id, x, y) in [(0, 3.0, 4.0), (1, -2.0, 5.0)] {
+            builder.add_node_at(id, x, y, 1.0).unwrap();
+        }
+        builder.add_edge(0, 1, 1.0).unwrap();
+        let graph = builder.build();
+        let placed = place(&graph.nodes, &graph.relations, &previous, None);
+        assert_eq!(
+            placed,
+            Placed {
+                kept: 0,
+                added: 0,
+                unplaced: 2,
+                removed: 1
+            }
+        );
+        assert_eq!(read_positions(&graph.nodes), vec![(3.0, 4.0), (-2.0, 5.0)]);
+        assert!(graph.nodes.iter().all(|e| e.anchor().is_none()));
+    }
+
+    #[test]
+    fn keeps_old_nodes_without_anchor() {
+        let mut builder = GraphBuilder::new();
+        builder.add_node_at(0, 5.0, 5.0, 1.0).unwrap();
+        let old = builder.build();
+        let previous = Layout::from_graph(&old.nodes, &old.relations);
+
+        let mut builder = GraphBuilder::new();
+        builder
+            .add_node_at(0, 0.0, 0.0, 1.0)
+            .unwrap()
+            .add_node_at(1, 9.0, 9.0, 1.0)
+            .unwrap();
+        builder.add_edge(0, 1, 1.0).unwrap();
+        let graph = builder.build();
+        let placed = place(&graph.nodes, &graph.relations, &previous, None);
+        assert_eq!((placed.kept, placed.added), (1, 1));
+        assert_eq!(read_positions(&graph.nodes)[0], (5.0, 5.0));
+        assert!(graph.nodes.iter().all(|e| e.anchor().is_none()));
+    }
+}
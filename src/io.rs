@@ -1,17 +1,28 @@
-use std::{io::Read, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
 
 use nohash_hasher::IntMap;
-use rand::{Rng, RngCore, SeedableRng};
 use serde::{Deserialize, Serialize};
 
-use crate::model::{Node, Relation};
+use crate::{
+    builder::{GraphBuilder, GraphError},
+    model::Graph,
+    sim::StepStats,
+};
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct NodeRecord {
     id: usize,
     weight: f32,
+    #[serde(default)]
+    label: Option<String>,
 }
 
+/// Columns of the node file that are not passed through as attributes.
+const NODE_COLUMNS: [&str; 3] = ["id", "weight", "label"];
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 struct RelationRecord {
     id: usize,
@@ -20,58 +31,135 @@ struct RelationRecord {
     weight: f32,
 }
 
-fn generate_coordinate<R: RngCore>(mut rng: R) -> f32 {
-    loop {
-        let tmp: f32 = rng.gen_range(0.0..100.0);
-        if tmp.is_normal() {
-            return tmp;
+fn read_nodes<R: Read>(reader: R, builder: &mut GraphBuilder) -> std::io::Result<()> {
+    let mut rdr = csv::Reader::from_reader(reader);
+
+    let headers = rdr.headers()?.clone();
+
+    for result in rdr.records() {
+        let raw = result?;
+        let record: NodeRecord = raw.deserialize(Some(&headers))?;
+        let attributes: BTreeMap<String, String> = headers
+            .iter()
+            .zip(raw.iter())
+            .filter(|(column, _)| !NODE_COLUMNS.contains(column))
+            .map(|(column, value)| (column.to_string(), value.to_string()))
+            .collect();
+        builder.add_node(record.id, record.weight)?;
+        builder.attributes(record.id, attributes)?;
+        if let Some(label) = record.label {
+            builder.label(record.id, label)?;
         }
     }
+
+    Ok(())
 }
 
-fn read_nodes<R: Read>(reader: R) -> std::io::Result<IntMap<usize, Arc<Node>>> {
+/// Rows sharing an id are rejected, they would otherwise become parallel springs.
+fn read_relations<R: Read>(reader: R, builder: &mut GraphBuilder) -> std::io::Result<()> {
     let mut rdr = csv::Reader::from_reader(reader);
-
-    let mut res = IntMap::default();
-    let mut rng = rand::rngs::SmallRng::from_seed([0u8; 32]);
+    let mut seen = IntMap::default();
 
     for result in rdr.deserialize() {
-        let record: NodeRecord = result?;
-        let id = record.id;
-        let (x, y) = (generate_coordinate(&mut rng), generate_coordinate(&mut rng));
-        let node = Arc::new(Node::new(record.id, x, y, record.weight));
-        res.insert(id, node);
+        let record: RelationRecord = result?;
+        if seen.insert(record.id, ()).is_some() {
+            return Err(GraphError::DuplicateRelation(record.id).into());
+        }
+        builder.add_edge(record.from, record.to, record.weight)?;
     }
+    Ok(())
+}
 
-    Ok(res)
+pub fn read_all<N: Read, R: Read>(node_reader: N, relation_reader: R) -> std::io::Result<Graph> {
+    let mut builder = GraphBuilder::new();
+    read_nodes(node_reader, &mut builder)?;
+    read_relations(relation_reader, &mut builder)?;
+    Ok(builder.build())
 }
 
-fn read_relations<R: Read>(
-    reader: R,
-    nodes: &mut IntMap<usize, Arc<Node>>,
-) -> std::io::Result<IntMap<usize, Arc<Relation>>> {
-    let mut rdr = csv::Reader::from_reader(reader);
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+struct TraceRecord {
+    step: usize,
+    energy: Option<f32>,
+    displacement: f32,
+    max_displacement: f32,
+    duration_ms: f64,
+}
 
-    let mut res = IntMap::default();
+/// Writes the statistics of simulation steps as CSV rows.
+pub struct TraceWriter<W: Write> {
+    writer: csv::Writer<W>,
+}
 
-    for result in rdr.deserialize() {
-        let record: RelationRecord = result?;
-        let to_node = nodes[&record.to].clone();
-        let from_node = nodes[&record.from].clone();
-        let relation = Arc::new(Relation::new(record.weight, from_node, to_node));
-        relation.register();
-        res.insert(record.id, relation);
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(writer),
+        }
+    }
+
+    /// Writes one row of the statistics of a batch, the energy is left empty if it is missing.
+    pub fn write(&mut self, stats: &StepStats) -> std::io::Result<()> {
+        self.writer.serialize(TraceRecord {
+            step: stats.step,
+            energy: stats.energy,
+            displacement: stats.displacement,
+            max_displacement: stats.max_displacement,
+            duration_ms: stats.duration.as_secs_f64() * 1000.0,
+        })?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
     }
-    Ok(res)
 }
 
-pub fn read_all<N: Read, R: Read>(
-    node_reader: N,
-    relation_reader: R,
-) -> std::io::Result<(Vec<Arc<Node>>, Vec<Arc<Relation>>)> {
-    let mut nodes = read_nodes(node_reader)?;
-    let relations = read_relations(relation_reader, &mut nodes)?;
-    let ret_nodes = nodes.into_values().collect();
-    let ret_relations = relations.into_values().collect();
-    Ok((ret_nodes, ret_relations))
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::sim::StepStats;
+
+    use super::{read_all, TraceWriter};
+
+    const NODES: &str = "id,weight,label\n1,1,Berlin\n2,1,Hamburg\n";
+
+    #[test]
+    fn rejects_duplicate_relations() {
+        let relations = "id,from,to,weight\n1,1,2,1\n1,2,1,1\n";
+        let error = read_all(NODES.as_bytes(), relations.as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "relation 1 was added twice");
+
+        let graph = read_all(
+            NODES.as_bytes(),
+            "id,from,to,weight\n1,1,2,1\n2,2,1,1\n".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(graph.relations.len(), 2);
+    }
+
+    #[test]
+    fn writes_trace_rows() {
+        let mut output = Vec::new();
+        let mut trace = TraceWriter::new(&mut output);
+        for (step, energy) in [(10, 4.5), (20, 2.25)] {
+            let stats = StepStats {
+                step,
+                displacement: 1.5,
+                max_displacement: 0.5,
+                duration: Duration::from_micros(250),
+                energy: Some(energy),
+            };
+            trace.write(&stats).unwrap();
+        }
+        trace.flush().unwrap();
+        drop(trace);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "step,energy,displacement,max_displacement,duration_ms\n\
+             10,4.5,1.5,0.5,0.25\n\
+             20,2.25,1.5,0.5,0.25\n"
+        );
+    }
 }
@@ -0,0 +1,469 @@
+//! Placement of node labels in the rendered image.
+
+use std::{collections::HashMap, f32::consts::FRAC_1_SQRT_2, sync::Arc};
+
+use crate::{
+    adjacency::Adjacency,
+    model::{Node, Relation},
+    overlap::label_extent,
+};
+
+/// Directions of the candidate positions around a node, the preferred ones first: the four
+/// corners as in cartography, then the sides.
+const DIRECTIONS: [(f32, f32); 8] = [
+    (FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+    (-FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+    (FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+    (-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, -1.0),
+    (0.0, 1.0),
+];
+/// Rings of candidates further out tried for labels with a leader line.
+const LEADER_RINGS: usize = 3;
+/// Distance between two rings of leader line candidates in font sizes.
+const LEADER_STEP: f32 = 2.0;
+/// Side of a cell of the spatial index in font sizes.
+const CELL_SIZE: f32 = 4.0;
+
+/// Line between two points.
+pub type Segment = ((f32, f32), (f32, f32));
+
+/// What decides which labels are placed first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Heavier nodes first.
+    Weight,
+    /// Nodes with more relations first.
+    Degree,
+}
+
+/// Label of one node, waiting to be placed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub content: String,
+    pub x: f32,
+    pub y: f32,
+    /// Radius of the circle of the node, the label is set outside of it.
+    pub radius: f32,
+    /// Labels with a higher priority are placed first.
+    pub priority: f32,
+}
+
+/// Labels of all labelled nodes at their layout positions.
+pub fn labels(nodes: &[Arc<Node>], relations: &[Arc<Relation>], priority: Priority) -> Vec<Label> {
+    let degrees = match priority {
+        Priority::Weight => None,
+        Priority::Degree => Some(Adjacency::from_graph(nodes, relations)),
+    };
+    nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, node)| {
+            let content = node.label.clone()?;
+            let loc = *node.loc.read().unwrap();
+            Some(Label {
+                content,
+                x: loc.x,
+                y: loc.y,
+                radius: node.radius(),
+                priority: match &degrees {
+                    None => node.weight.abs(),
+                    Some(adjacency) => adjacency.degree(i) as f32,
+                },
+            })
+        })
+        .collect()
+}
+
+/// A label where it will be drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacedLabel {
+    pub content: String,
+    /// Centre of the text.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// Line from the circle of the node to a label set further away.
+    pub leader: Option<Segment>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Rect {
+    min_x: f32,
+    min_y: f32,
+    max_x: f32,
+    max_y: f32,
+}
+
+impl Rect {
+    fn around(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            min_x: x - width / 2.0,
+            min_y: y - height / 2.0,
+            max_x: x + width / 2.0,
+            max_y: y + height / 2.0,
+        }
+    }
+
+    fn grow(self, by: f32) -> Self {
+        Self {
+            min_x: self.min_x - by,
+            min_y: self.min_y - by,
+            max_x: self.max_x + by,
+            max_y: self.max_y + by,
+        }
+    }
+
+    fn intersects(&self, other: &Rect) -> bool {
+        self.min_x < other.max_x
+            && other.min_x < self.max_x
+            && self.min_y < other.max_y
+            && other.min_y < self.max_y
+    }
+
+    fn within(&self, width: f32, height: f32) -> bool {
+        self.min_x >= 0.0 && self.min_y >= 0.0 && self.max_x <= width && self.max_y <= height
+    }
+
+    fn touches_circle(&self, (x, y): (f32, f32), radius: f32) -> bool {
+        let dx = x - x.clamp(self.min_x, self.max_x);
+        let dy = y - y.clamp(self.min_y, self.max_y);
+        dx * dx + dy * dy < radius * radius
+    }
+
+    /// Whether the segment from `a` to `b` runs through the rectangle, by Liang-Barsky clipping.
+    fn crossed_by(&self, a: (f32, f32), b: (f32, f32)) -> bool {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let (mut enter, mut leave) = (0.0f32, 1.0f32);
+        for (p, q) in [
+            (-dx, a.0 - self.min_x),
+            (dx, self.max_x - a.0),
+            (-dy, a.1 - self.min_y),
+            (dy, self.max_y - a.1),
+        ] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return false;
+                }
+            } else {
+                let t = q / p;
+                if p < 0.0 {
+                    enter = enter.max(t);
+                } else {
+                    leave = leave.min(t);
+                }
+            }
+        }
+        enter < leave
+    }
+
+    fn closest_to(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            x.clamp(self.min_x, self.max_x),
+            y.clamp(self.min_y, self.max_y),
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Obstacle {
+    Circle(usize),
+    Segment(usize),
+    Label(usize),
+}
+
+/// Uniform grid of the obstacles, so a candidate is only tested against its surroundings.
+struct Grid {
+    cell: f32,
+    cells: HashMap<(i32, i32), Vec<Obstacle>>,
+}
+
+impl Grid {
+    fn cells(&self, rect: &Rect) -> impl Iterator<Item = (i32, i32)> {
+        let to_cell = |v: f32, cell: f32| (v / cell).floor() as i32;
+        let (x0, x1) = (
+            to_cell(rect.min_x, self.cell),
+            to_cell(rect.max_x, self.cell),
+        );
+        let (y0, y1) = (
+            to_cell(rect.min_y, self.cell),
+            to_cell(rect.max_y, self.cell),
+        );
+        (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+    }
+
+    fn insert(&mut self, rect: &Rect, obstacle: Obstacle) {
+        let cells: Vec<(i32, i32)> = self.cells(rect).collect();
+        for cell in cells {
+            self.cells.entry(cell).or_default().push(obstacle);
+        }
+    }
+
+    fn near(&self, rect: &Rect) -> Vec<Obstacle> {
+        let mut found: Vec<Obstacle> = self
+            .cells(rect)
+            .filter_map(|e| self.cells.get(&e))
+            .flatten()
+            .copied()
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
+
+/// Greedy label placement.
+///
+/// Labels are placed one after the other by falling priority. Each tries the candidate positions
+/// around its node and takes the first one that stays inside the image and hits neither a placed
+/// label nor a circle, preferring candidates that cross fewer edges. Labels without such a spot
+/// are dropped, or set further away with a leader line if `leader_lines` is enabled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LabelPlacement {
+    /// Font size in pixels.
+    pub font_size: f32,
+    /// Space kept free around every label.
+    pub padding: f32,
+    pub leader_lines: bool,
+}
+
+impl Default for LabelPlacement {
+    fn default() -> Self {
+        Self {
+            font_size: 12.0,
+            padding: 2.0,
+            leader_lines: false,
+        }
+    }
+}
+
+impl LabelPlacement {
+    /// Places `labels` in an image of `width` by `height` pixels, avoiding the given circles and
+    /// segments. All coordinates are in pixels.
+    pub fn place(
+        &self,
+        labels: &[Label],
+        circles: &[((f32, f32), f32)],
+        segments: &[Segment],
+        width: f32,
+        height: f32,
+    ) -> Vec<PlacedLabel> {
+        let mut grid = Grid {
+            cell: (self.font_size * CELL_SIZE).max(1.0),
+            cells: HashMap::new(),
+        };
+        for (i, &((x, y), radius)) in circles.iter().enumerate() {
+            grid.insert(
+                &Rect::around(x, y, 2.0 * radius, 2.0 * radius),
+                Obstacle::Circle(i),
+            );
+        }
+        let mut segments = segments.to_vec();
+        for (i, &(a, b)) in segments.iter().enumerate() {
+            grid.insert(&segment_box(a, b), Obstacle::Segment(i));
+        }
+
+        let mut order: Vec<usize> = (0..labels.len()).collect();
+        order.sort_by(|a, b| labels[*b].priority.total_cmp(&labels[*a].priority));
+        let mut boxes: Vec<Rect> = Vec::new();
+        let mut placed = Vec::new();
+        for i in order {
+            let label = &labels[i];
+            let (w, h) = label_extent(&label.content, self.font_size);
+            let free = |rect: &Rect, leader: Option<Segment>| {
+                if !rect.within(width, height) {
+                    return None;
+                }
+                let padded = rect.grow(self.padding);
+                let mut crossings = 0;
+                for obstacle in grid.near(&padded) {
+                    match obstacle {
+                        Obstacle::Circle(c) => {
+                            let (centre, radius) = circles[c];
+                            if padded.touches_circle(centre, radius) {
+                                return None;
+                            }
+                        }
+                        Obstacle::Label(l) => {
+                            if padded.intersects(&boxes[l]) {
+                                return None;
+                            }
+                        }
+                        Obstacle::Segment(s) => {
+                            let (a, b) = segments[s];
+                            if padded.crossed_by(a, b) {
+                                crossings += 1;
+                            }
+                        }
+                    }
+                }
+                if let Some((a, b)) = leader {
+                    if boxes.iter().any(|e| e.crossed_by(a, b)) {
+                        return None;
+                    }
+                }
+                Some(crossings)
+            };
+
+            // Twice the padding keeps the padded box clear of the circle of the node itself.
+            let distance = label.radius + 2.0 * self.padding;
+            let near = DIRECTIONS
+                .iter()
+                .filter_map(|&direction| {
+                    let rect = candidate(label, direction, distance, w, h);
+                    free(&rect, None).map(|crossings| (crossings, rect, None))
+                })
+                .min_by_key(|e| e.0);
+            let chosen = near.or_else(|| {
+                if !self.leader_lines {
+                    return None;
+                }
+                (1..=LEADER_RINGS).find_map(|ring| {
+                    let distance = distance + ring as f32 * LEADER_STEP * self.font_size;
+                    DIRECTIONS
+                        .iter()
+                        .filter_map(|&(dx, dy)| {
+                            let rect = candidate(label, (dx, dy), distance, w, h);
+                            let start = (label.x + dx * label.radius, label.y + dy * label.radius);
+                            let leader = Some((start, rect.closest_to((label.x, label.y))));
+                            free(&rect, leader).map(|crossings| (crossings, rect, leader))
+                        })
+                        .min_by_key(|e| e.0)
+                })
+            });
+
+            let Some((_, rect, leader)) = chosen else {
+                continue;
+            };
+            grid.insert(&rect.grow(self.padding), Obstacle::Label(boxes.len()));
+            boxes.push(rect.grow(self.padding));
+            if let Some((a, b)) = leader {
+                grid.insert(&segment_box(a, b), Obstacle::Segment(segments.len()));
+                segments.push((a, b));
+            }
+            placed.push(PlacedLabel {
+                content: label.content.clone(),
+                x: (rect.min_x + rect.max_x) / 2.0,
+                y: (rect.min_y + rect.max_y) / 2.0,
+                width: w,
+                height: h,
+                leader,
+            });
+        }
+        placed
+    }
+}
+
+fn segment_box(a: (f32, f32), b: (f32, f32)) -> Rect {
+    Rect {
+        min_x: a.0.min(b.0),
+        min_y: a.1.min(b.1),
+        max_x: a.0.max(b.0),
+        max_y: a.1.max(b.1),
+    }
+}
+
+/// Box of a label touching the point `distance` away from the node in `direction`.
+fn candidate(label: &Label, (dx, dy): (f32, f32), distance: f32, w: f32, h: f32) -> Rect {
+    let side = |d: f32| if d.abs() < 1.0E-6 { 0.0 } else { d.signum() };
+    Rect::around(
+        label.x + dx * distance + side(dx) * w / 2.0,
+        label.y + dy * distance + side(dy) * h / 2.0,
+        w,
+        h,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::{Element, Renderer};
+
+    use super::{Label, LabelPlacement, Rect};
+
+    fn label(content: &str, x: f32, y: f32, priority: f32) -> Label {
+        Label {
+            content: content.to_string(),
+            x,
+            y,
+            radius: 2.0,
+            priority,
+        }
+    }
+
+    #[test]
+    fn keeps_important_labels_apart() {
+        let labels: Vec<Label> = (0..6)
+            .map(|i| label("Station", 20.0 + i as f32 * 3.0, 50.0, i as f32))
+            .collect();
+        let circles: Vec<((f32, f32), f32)> =
+            labels.iter().map(|e| ((e.x, e.y), e.radius)).collect();
+        let placed = LabelPlacement::default().place(&labels, &circles, &[], 100.0, 100.0);
+
+        assert!(placed.len() < labels.len(), "Crowded labels are dropped");
+        // The label of the last node has the highest priority and gets the first spot.
+        let first = &placed[0];
+        assert!(first.x > labels[5].x && first.y < labels[5].y);
+        let boxes: Vec<Rect> = placed
+            .iter()
+            .map(|e| Rect::around(e.x, e.y, e.width, e.height))
+            .collect();
+        for (i, a) in boxes.iter().enumerate() {
+            assert!(a.within(100.0, 100.0));
+            assert!(boxes[i + 1..].iter().all(|b| !a.intersects(b)));
+            assert!(circles.iter().all(|(c, r)| !a.touches_circle(*c, *r)));
+        }
+    }
+
+    #[test]
+    fn avoids_edges_and_sets_leader_lines() {
+        // Edges leave the node to the upper corners, the label goes below it.
+        let labels = [label("Hub", 50.0, 50.0, 1.0)];
+        let edges = [((50.0, 50.0), (90.0, 10.0)), ((50.0, 50.0), (10.0, 10.0))];
+        let placed =
+            LabelPlacement::default().place(&labels, &[((50.0, 50.0), 2.0)], &edges, 100.0, 100.0);
+        assert!(placed[0].y > 50.0);
+
+        // A ring of large circles leaves no room next to the node.
+        let mut circles = vec![((50.0, 50.0), 2.0)];
+        for i in 0..16 {
+            let angle = i as f32 * std::f32::consts::PI / 8.0;
+            circles.push(((50.0 + 9.0 * angle.cos(), 50.0 + 9.0 * angle.sin()), 4.0));
+        }
+        let dropped = LabelPlacement::default().place(&labels, &circles, &[], 100.0, 100.0);
+        assert!(dropped.is_empty());
+        let leader = LabelPlacement {
+            leader_lines: true,
+            ..LabelPlacement::default()
+        };
+        let placed = leader.place(&labels, &circles, &[], 100.0, 100.0);
+        assert_eq!(placed.len(), 1);
+        assert!(placed[0].leader.is_some());
+    }
+
+    #[test]
+    fn renders_escaped_text() {
+        let mut renderer = Renderer::new();
+        renderer.add_element(Element::Circle {
+            radius: 2.0,
+            x: 0.0,
+            y: 0.0,
+            fill: None,
+        });
+        renderer.add_element(Element::Circle {
+            radius: 2.0,
+            x: 100.0,
+            y: 100.0,
+            fill: None,
+        });
+        renderer.add_labels(
+            vec![label("Rail & Road", 0.0, 0.0, 1.0)],
+            LabelPlacement::default(),
+        );
+        let svg = renderer.render(200.0, 200.0);
+        assert!(svg.contains(r#"font-size="12""#));
+        assert!(svg.contains(">Rail &amp; Road</text>"));
+    }
+}
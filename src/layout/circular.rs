@@ -0,0 +1,285 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::{
+    adjacency::Adjacency,
+    builder::INITIAL_EXTENT,
+    model::{Graph, Node, Relation},
+};
+
+use super::{write_positions, LayoutEngine};
+
+/// Places all nodes evenly on one circle.
+///
+/// The nodes start in depth first order, which keeps paths and the members of a component next
+/// to each other. Neighbouring nodes on the circle are then swapped as long as that removes
+/// crossings between the chords. The result only depends on the graph, which makes it a useful
+/// baseline and starting point for the other algorithms.
+pub struct Circular {
+    nodes: Vec<Arc<Node>>,
+    relations: Vec<Arc<Relation>>,
+    /// Radius of the circle, by default the same area random placement covers.
+    pub radius: f32,
+    /// Most passes of neighbour swaps, whatever number of iterations is asked for. A pass costs
+    /// the product of the degrees of every pair of neighbours on the circle.
+    pub max_passes: usize,
+}
+
+impl Circular {
+    pub fn new(graph: Graph) -> Self {
+        Self {
+            nodes: graph.nodes,
+            relations: graph.relations,
+            radius: INITIAL_EXTENT / 2.0,
+            max_passes: MAX_PASSES,
+        }
+    }
+}
+
+/// Default of [`Circular::max_passes`].
+const MAX_PASSES: usize = 50;
+
+/// Depth first order of all components, each starting at a node of the lowest degree.
+fn depth_first_order(adjacency: &Adjacency) -> Vec<usize> {
+    let mut starts: Vec<usize> = (0..adjacency.len()).collect();
+    starts.sort_by_key(|e| adjacency.degree(*e));
+    let mut visited = vec![false; adjacency.len()];
+    let mut order = Vec::with_capacity(adjacency.len());
+    for start in starts {
+        let mut stack = vec![start];
+        while let Some(node) = stack.pop() {
+            if visited[node] {
+                continue;
+            }
+            visited[node] = true;
+            order.push(node);
+            stack.extend(
+                adjacency
+                    .neighbours(node)
+                    .iter()
+                    .rev()
+                    .map(|(e, _)| *e)
+                    .filter(|e| !visited[*e]),
+            );
+        }
+    }
+    order
+}
+
+/// Whether the chords `a`-`b` and `c`-`d` between slots of the circle cross.
+fn chords_cross((a, b): (usize, usize), (c, d): (usize, usize)) -> bool {
+    if a == c || a == d || b == c || b == d {
+        return false;
+    }
+    let (low, high) = (a.min(b), a.max(b));
+    let inside = |e: usize| low < e && e < high;
+    inside(c) != inside(d)
+}
+
+struct Chords {
+    edges: Vec<(usize, usize)>,
+    /// Edges touching every node.
+    incident: Vec<Vec<usize>>,
+    /// Slot of every node on the circle.
+    slots: Vec<usize>,
+}
+
+impl Chords {
+    fn new(adjacency: &Adjacency, order: &[usize]) -> Self {
+        let mut edges = Vec::new();
+        let mut incident = vec![Vec::new(); adjacency.len()];
+        for from in 0..adjacency.len() {
+            for &(to, _) in adjacency.neighbours(from) {
+                if from < to {
+                    incident[from].push(edges.len());
+                    incident[to].push(edges.len());
+                    edges.push((from, to));
+                }
+            }
+        }
+        let mut slots = vec![0; order.len()];
+        for (slot, &node) in order.iter().enumerate() {
+            slots[node] = slot;
+        }
+        Self {
+            edges,
+            incident,
+            slots,
+        }
+    }
+
+    fn chord(&self, edge: usize) -> (usize, usize) {
+        let (a, b) = self.edges[edge];
+        (self.slots[a], self.slots[b])
+    }
+
+    /// Crossing pairs of one edge touching `a` and another one touching `b`.
+    ///
+    /// Swapping two nodes in neighbouring slots only changes the order of their own endpoints,
+    /// so these are the only pairs which can start or stop crossing.
+    fn crossings_between(&self, a: usize, b: usize) -> usize {
+        let mut count = 0;
+        for &e in &self.incident[a] {
+            for &f in &self.incident[b] {
+                if e != f && chords_cross(self.chord(e), self.chord(f)) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Number of crossing pairs, counted as interleaving intervals in `O(m log n)`.
+    fn crossings(&self) -> usize {
+        let mut intervals: Vec<(usize, usize)> = (0..self.edges.len())
+            .map(|e| {
+                let (a, b) = self.chord(e);
+                (a.min(b), a.max(b))
+            })
+            .collect();
+        intervals.sort_unstable();
+        // Fenwick tree over the right ends of the intervals starting further left.
+        let mut tree = vec![0; self.slots.len() + 1];
+        let count_below = |tree: &[usize], end: usize| {
+            let (mut i, mut count) = (end, 0);
+            while i > 0 {
+                count += tree[i];
+                i &= i - 1;
+            }
+            count
+        };
+        let mut count = 0;
+        for group in intervals.chunk_by(|x, y| x.0 == y.0) {
+            // Intervals sharing their left end meet there and do not cross each other.
+            for &(low, high) in group {
+                count += count_below(&tree, high) - count_below(&tree, low + 1);
+            }
+            for &(_, high) in group {
+                let mut i = high + 1;
+                while i < tree.len() {
+                    tree[i] += 1;
+                    i += i & i.wrapping_neg();
+                }
+            }
+        }
+        count
+    }
+}
+
+impl LayoutEngine for Circular {
+    /// Runs at most `iterations` passes of neighbour swaps, but no more than
+    /// [`max_passes`](Circular::max_passes), and returns the number of crossings.
+    fn run(&self, iterations: usize) -> std::io::Result<f32> {
+        let adjacency = Adjacency::from_graph(&self.nodes, &self.relations);
+        let mut order = depth_first_order(&adjacency);
+        let mut chords = Chords::new(&adjacency, &order);
+        let n = order.len();
+        for _ in 0..iterations.min(self.max_passes) {
+            let mut improved = false;
+            for slot in 0..n.saturating_sub(1) {
+                let (a, b) = (order[slot], order[slot + 1]);
+                let before = chords.crossings_between(a, b);
+                chords.slots.swap(a, b);
+                if chords.crossings_between(a, b) < before {
+                    order.swap(slot, slot + 1);
+                    improved = true;
+                } else {
+                    chords.slots.swap(a, b);
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+
+        let positions: Vec<(f32, f32)> = (0..n)
+            .map(|node| {
+                let angle = 2.0 * PI * chords.slots[node] as f32 / n as f32;
+                (
+                    self.radius * (1.0 + angle.cos()),
+                    self.radius * (1.0 + angle.sin()),
+                )
+            })
+            .collect();
+        write_positions(&self.nodes, &positions);
+        Ok(chords.crossings() as f32)
+    }
+
+    fn nodes(&self) -> &[Arc<Node>] {
+        &self.nodes
+    }
+
+    fn relations(&self) -> &[Arc<Relation>] {
+        &self.relations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{adjacency::Adjacency, builder::GraphBuilder, layout::LayoutEngine};
+
+    use super::{chords_cross, Chords, Circular};
+
+    #[test]
+    fn untangles_cycle() {
+        let mut builder = GraphBuilder::new();
+        let order = [0, 3, 1, 4, 2, 5];
+        for i in 0..6 {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        for i in 0..6 {
+            builder.add_edge(order[i], order[(i + 1) % 6], 1.0).unwrap();
+        }
+        builder.add_edge(0, 1, 1.0).unwrap();
+        let engine = Circular::new(builder.build());
+        assert_eq!(engine.run(100).unwrap(), 0.0);
+
+        let layout = engine.layout();
+        let centre = (engine.radius, engine.radius);
+        for node in &layout.nodes {
+            let distance = (node.x - centre.0).hypot(node.y - centre.1);
+            assert!((distance - engine.radius).abs() < 1.0E-3);
+        }
+        // Deterministic, a second run ends up in the same place.
+        engine.run(100).unwrap();
+        assert_eq!(engine.layout().nodes[3].x, layout.nodes[3].x);
+    }
+
+    #[test]
+    fn counts_crossings_like_all_pairs() {
+        let mut builder = GraphBuilder::new();
+        for i in 0..8 {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        // A ring with chords from every node to 4 or 0.
+        let mut edges = Vec::new();
+        for i in 0..8 {
+            for (a, b) in [(i, (i + 1) % 8), (i, (i * 4 + 4) % 8)] {
+                let edge = (a.min(b), a.max(b));
+                if a != b && !edges.contains(&edge) {
+                    edges.push(edge);
+                    builder.add_edge(edge.0, edge.1, 1.0).unwrap();
+                }
+            }
+        }
+        let graph = builder.build();
+        let adjacency = Adjacency::from_graph(&graph.nodes, &graph.relations);
+        let order: Vec<usize> = (0..8).map(|e| e * 3 % 8).collect();
+        let chords = Chords::new(&adjacency, &order);
+        let mut expected = 0;
+        for e in 0..chords.edges.len() {
+            for f in e + 1..chords.edges.len() {
+                expected += chords_cross(chords.chord(e), chords.chord(f)) as usize;
+            }
+        }
+        assert!(expected > 10, "{expected}");
+        assert_eq!(chords.crossings(), expected);
+
+        // The swaps remove crossings, but are capped whatever the number of iterations.
+        let capped = Circular {
+            max_passes: 0,
+            ..Circular::new(graph.clone())
+        };
+        assert_eq!(capped.run(100).unwrap(), 9.0);
+        assert_eq!(Circular::new(graph).run(100).unwrap(), 2.0);
+    }
+}
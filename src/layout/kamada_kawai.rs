@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use crate::{
+    adjacency::Adjacency,
+    model::{Graph, Node, Relation},
+};
+
+use super::{distance_matrix, read_positions, write_positions, LayoutEngine};
+
+/// Kamada-Kawai spring layout.
+///
+/// Every pair of nodes is connected by a spring whose rest length is their weighted shortest
+/// path distance and whose stiffness falls off with the square of that distance. The energy is
+/// minimized by repeatedly moving the node with the largest gradient with Newton-Raphson steps.
+/// Time and memory are quadratic in the number of nodes, which makes it a good fit for graphs
+/// of up to a few hundred nodes.
+pub struct KamadaKawai {
+    nodes: Vec<Arc<Node>>,
+    relations: Vec<Arc<Relation>>,
+    /// Stiffness of the springs.
+    pub strength: f32,
+    /// Stops once no node has a gradient larger than this value.
+    pub tolerance: f32,
+    /// Upper bound of Newton-Raphson steps spent on a single node in a row.
+    pub newton_steps: usize,
+}
+
+impl KamadaKawai {
+    pub fn new(graph: Graph) -> Self {
+        Self {
+            nodes: graph.nodes,
+            relations: graph.relations,
+            strength: 1.0,
+            tolerance: 1.0E-3,
+            newton_steps: 50,
+        }
+    }
+}
+
+struct System {
+    positions: Vec<(f64, f64)>,
+    lengths: Vec<Vec<f64>>,
+    stiffness: Vec<Vec<f64>>,
+    gradients: Vec<(f64, f64)>,
+}
+
+/// Gradient of a spring with stiffness `k` and rest length `l` at its end `at`.
+fn spring_gradient(k: f64, l: f64, at: (f64, f64), other: (f64, f64)) -> (f64, f64) {
+    let (dx, dy) = (at.0 - other.0, at.1 - other.1);
+    let distance = dx.hypot(dy).max(f64::EPSILON);
+    (k * (dx - l * dx / distance), k * (dy - l * dy / distance))
+}
+
+impl System {
+    fn gradient(&self, i: usize) -> (f64, f64) {
+        (0..self.positions.len())
+            .filter(|j| *j != i)
+            .map(|j| {
+                spring_gradient(
+                    self.stiffness[i][j],
+                    self.lengths[i][j],
+                    self.positions[i],
+                    self.positions[j],
+                )
+            })
+            .fold((0.0, 0.0), |acc, e| (acc.0 + e.0, acc.1 + e.1))
+    }
+
+    /// Solves the Newton-Raphson system of node `m` for its next offset.
+    fn newton_offset(&self, m: usize) -> (f64, f64) {
+        let (x, y) = self.positions[m];
+        let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+        for i in (0..self.positions.len()).filter(|i| *i != m) {
+            let (dx, dy) = (x - self.positions[i].0, y - self.positions[i].1);
+            let cube = dx.hypot(dy).max(f64::EPSILON).powi(3);
+            let k = self.stiffness[m][i];
+            let l = self.lengths[m][i];
+            xx += k * (1.0 - l * dy * dy / cube);
+            yy += k * (1.0 - l * dx * dx / cube);
+            xy += k * l * dx * dy / cube;
+        }
+        let (gx, gy) = self.gradients[m];
+        let determinant = xx * yy - xy * xy;
+        if determinant.abs() < f64::EPSILON {
+            return (0.0, 0.0);
+        }
+        (
+            (xy * gy - yy * gx) / determinant,
+            (xy * gx - xx * gy) / determinant,
+        )
+    }
+
+    /// Moves node `m`, keeping the gradients of all other nodes up to date.
+    fn move_node(&mut self, m: usize, to: (f64, f64)) {
+        let from = self.positions[m];
+        for i in (0..self.positions.len()).filter(|i| *i != m) {
+            let (k, l, at) = (self.stiffness[i][m], self.lengths[i][m], self.positions[i]);
+            let old = spring_gradient(k, l, at, from);
+            let new = spring_gradient(k, l, at, to);
+            self.gradients[i].0 += new.0 - old.0;
+            self.gradients[i].1 += new.1 - old.1;
+        }
+        self.positions[m] = to;
+        self.gradients[m] = self.gradient(m);
+    }
+
+    fn energy(&self) -> f64 {
+        let n = self.positions.len();
+        let mut energy = 0.0;
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let (a, b) = (self.positions[i], self.positions[j]);
+                let distance = (a.0 - b.0).hypot(a.1 - b.1);
+                energy += 0.5 * self.stiffness[i][j] * (distance - self.lengths[i][j]).powi(2);
+            }
+        }
+        energy
+    }
+}
+
+fn magnitude(gradient: (f64, f64)) -> f64 {
+    gradient.0.hypot(gradient.1)
+}
+
+impl LayoutEngine for KamadaKawai {
+    /// Moves at most `iterations` nodes and returns the final spring energy.
+    fn run(&self, iterations: usize) -> std::io::Result<f32> {
+        let adjacency = Adjacency::from_graph(&self.nodes, &self.relations);
+        let lengths: Vec<Vec<f64>> = distance_matrix(&adjacency)
+            .into_iter()
+            .map(|row| row.into_iter().map(|e| e as f64).collect())
+            .collect();
+        let stiffness = lengths
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|e| {
+                        if *e > 0.0 {
+                            self.strength as f64 / e.powi(2)
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut system = System {
+            positions: read_positions(&self.nodes)
+                .into_iter()
+                .map(|(x, y)| (x as f64, y as f64))
+                .collect(),
+            lengths,
+            stiffness,
+            gradients: Vec::new(),
+        };
+        system.gradients = (0..system.positions.len())
+            .map(|i| system.gradient(i))
+            .collect();
+
+        let tolerance = self.tolerance as f64;
+        for _ in 0..iterations {
+            let Some((m, largest)) = system
+                .gradients
+                .iter()
+                .map(|e| magnitude(*e))
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+            else {
+                break;
+            };
+            if largest < tolerance {
+                break;
+            }
+            for _ in 0..self.newton_steps {
+                let (dx, dy) = system.newton_offset(m);
+                let (x, y) = system.positions[m];
+                system.move_node(m, (x + dx, y + dy));
+                if magnitude(system.gradients[m]) < tolerance {
+                    break;
+                }
+            }
+        }
+
+        let positions: Vec<(f32, f32)> = system
+            .positions
+            .iter()
+            .map(|(x, y)| (*x as f32, *y as f32))
+            .collect();
+        write_positions(&self.nodes, &positions);
+        Ok(system.energy() as f32)
+    }
+
+    fn nodes(&self) -> &[Arc<Node>] {
+        &self.nodes
+    }
+
+    fn relations(&self) -> &[Arc<Relation>] {
+        &self.relations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{builder::GraphBuilder, layout::LayoutEngine};
+
+    use super::KamadaKawai;
+
+    #[test]
+    fn lays_out_cycle_evenly() {
+        let mut builder = GraphBuilder::new();
+        let n = 6;
+        for i in 0..n {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        for i in 0..n {
+            builder.add_edge(i, (i + 1) % n, 1.0).unwrap();
+        }
+        let engine = KamadaKawai::new(builder.build());
+        let energy = engine.run(1000).unwrap();
+        assert!(energy < 0.5, "Energy is {energy}");
+
+        let layout = engine.layout();
+        for i in 0..n {
+            let (a, b) = (&layout.nodes[i], &layout.nodes[(i + 1) % n]);
+            let length = (a.x - b.x).hypot(a.y - b.y);
+            assert!((length - 1.0).abs() < 0.2, "Edge {i} has length {length}");
+        }
+    }
+}
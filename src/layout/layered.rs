@@ -0,0 +1,474 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use crossbeam::sync::ShardedLock;
+
+use crate::{
+    export::Layout,
+    model::{Graph, Node, Relation},
+    render::{Element, Part, Renderer},
+};
+
+use super::{node_index, write_positions, LayoutEngine};
+
+/// Passes of the coordinate assignment, alternating between downward and upward sweeps.
+const COORDINATE_PASSES: usize = 8;
+/// Crossing minimization gives up after this many sweeps without an improvement.
+const STALLED_SWEEPS: usize = 8;
+
+/// Hierarchical layout of directed graphs following Sugiyama, Tagawa and Toda.
+///
+/// Relations point from their `from` to their `to` node. Edges closing a cycle are reversed
+/// first, then every node is put on the layer after the longest path leading to it. Edges
+/// spanning several layers get a dummy node on every layer in between, which become the bend
+/// points of the edge. The order within the layers is improved with barycenter sweeps and the
+/// horizontal coordinates pull every node towards its neighbours while keeping the spacing.
+pub struct Layered {
+    nodes: Vec<Arc<Node>>,
+    relations: Vec<Arc<Relation>>,
+    /// Vertical distance between two layers.
+    pub layer_spacing: f32,
+    /// Minimum horizontal gap between the circles of two nodes on the same layer.
+    pub node_spacing: f32,
+    bends: ShardedLock<Vec<Vec<(f32, f32)>>>,
+}
+
+impl Layered {
+    pub fn new(graph: Graph) -> Self {
+        Self {
+            nodes: graph.nodes,
+            relations: graph.relations,
+            layer_spacing: 100.0,
+            node_spacing: 50.0,
+            bends: ShardedLock::new(Vec::new()),
+        }
+    }
+
+    /// Bend points of every relation from the last run, in the order of the relations.
+    pub fn bends(&self) -> Vec<Vec<(f32, f32)>> {
+        self.bends.read().unwrap().clone()
+    }
+
+    fn edges(&self) -> Vec<(usize, usize)> {
+        let index = node_index(&self.nodes);
+        self.relations
+            .iter()
+            .map(|e| (index[&e.from.id()], index[&e.to.id()]))
+            .collect()
+    }
+}
+
+/// Marks the edges closing a cycle during a depth first search. Reversing them leaves the graph
+/// without cycles.
+fn feedback_edges(n: usize, edges: &[(usize, usize)]) -> Vec<bool> {
+    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for (e, &(from, _)) in edges.iter().enumerate() {
+        outgoing[from].push(e);
+    }
+    // 0 is unvisited, 1 on the stack and 2 finished.
+    let mut state = vec![0u8; n];
+    let mut reversed = vec![false; edges.len()];
+    for root in 0..n {
+        if state[root] != 0 {
+            continue;
+        }
+        state[root] = 1;
+        let mut stack = vec![(root, 0)];
+        while let Some(&(node, next)) = stack.last() {
+            match outgoing[node].get(next) {
+                Some(&e) => {
+                    stack.last_mut().unwrap().1 += 1;
+                    let target = edges[e].1;
+                    match state[target] {
+                        0 => {
+                            state[target] = 1;
+                            stack.push((target, 0));
+                        }
+                        1 => reversed[e] = true,
+                        _ => {}
+                    }
+                }
+                None => {
+                    state[node] = 2;
+                    stack.pop();
+                }
+            }
+        }
+    }
+    reversed
+}
+
+/// Longest path layering of an acyclic graph, sources end up on layer 0.
+fn assign_layers(n: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut incoming = vec![0; n];
+    for &(from, to) in edges {
+        outgoing[from].push(to);
+        incoming[to] += 1;
+    }
+    let mut layers = vec![0; n];
+    let mut queue: VecDeque<usize> = (0..n).filter(|e| incoming[*e] == 0).collect();
+    while let Some(node) = queue.pop_front() {
+        for &target in &outgoing[node] {
+            layers[target] = layers[target].max(layers[node] + 1);
+            incoming[target] -= 1;
+            if incoming[target] == 0 {
+                queue.push_back(target);
+            }
+        }
+    }
+    layers
+}
+
+/// Proper layered graph, every edge connects two adjacent layers.
+struct Hierarchy {
+    layers: Vec<Vec<usize>>,
+    /// Neighbours of every vertex on the layer above.
+    up: Vec<Vec<usize>>,
+    /// Neighbours of every vertex on the layer below.
+    down: Vec<Vec<usize>>,
+    /// Radius of every vertex, dummy vertices have none.
+    radii: Vec<f32>,
+}
+
+impl Hierarchy {
+    /// Builds the hierarchy, returning it together with the chain of vertices of every edge.
+    fn new(layer_of: &[usize], radii: &[f32], edges: &[(usize, usize)]) -> (Self, Vec<Vec<usize>>) {
+        let mut layer_of = layer_of.to_vec();
+        let mut radii = radii.to_vec();
+        let chains: Vec<Vec<usize>> = edges
+            .iter()
+            .map(|&(from, to)| {
+                if from == to {
+                    return Vec::new();
+                }
+                let mut chain = vec![from];
+                for layer in layer_of[from] + 1..layer_of[to] {
+                    chain.push(layer_of.len());
+                    layer_of.push(layer);
+                    radii.push(0.0);
+                }
+                chain.push(to);
+                chain
+            })
+            .collect();
+
+        let count = layer_of.len();
+        let mut up = vec![Vec::new(); count];
+        let mut down = vec![Vec::new(); count];
+        for pair in chains.iter().flat_map(|e| e.windows(2)) {
+            down[pair[0]].push(pair[1]);
+            up[pair[1]].push(pair[0]);
+        }
+        let mut layers = vec![Vec::new(); layer_of.iter().max().map_or(0, |e| e + 1)];
+        for (vertex, &layer) in layer_of.iter().enumerate() {
+            layers[layer].push(vertex);
+        }
+        (
+            Self {
+                layers,
+                up,
+                down,
+                radii,
+            },
+            chains,
+        )
+    }
+
+    fn positions(&self) -> Vec<usize> {
+        let mut positions = vec![0; self.radii.len()];
+        for layer in &self.layers {
+            for (i, &vertex) in layer.iter().enumerate() {
+                positions[vertex] = i;
+            }
+        }
+        positions
+    }
+
+    fn crossings(&self) -> usize {
+        let positions = self.positions();
+        self.layers
+            .iter()
+            .map(|layer| {
+                let mut segments: Vec<(usize, usize)> = layer
+                    .iter()
+                    .flat_map(|&e| self.down[e].iter().map(move |&d| (e, d)))
+                    .map(|(a, b)| (positions[a], positions[b]))
+                    .collect();
+                segments.sort_unstable();
+                let mut count = 0;
+                for (i, a) in segments.iter().enumerate() {
+                    count += segments[i + 1..]
+                        .iter()
+                        .filter(|b| b.0 > a.0 && b.1 < a.1)
+                        .count();
+                }
+                count
+            })
+            .sum()
+    }
+
+    /// Sorts every layer by the barycenter of its neighbours on the previous one.
+    fn sweep(&mut self, downwards: bool) {
+        let mut positions = self.positions();
+        let order: Vec<usize> = if downwards {
+            (1..self.layers.len()).collect()
+        } else {
+            (0..self.layers.len().saturating_sub(1)).rev().collect()
+        };
+        for l in order {
+            let reference = if downwards { &self.up } else { &self.down };
+            let keys: Vec<f32> = self.layers[l]
+                .iter()
+                .map(|&v| {
+                    let neighbours = &reference[v];
+                    if neighbours.is_empty() {
+                        positions[v] as f32
+                    } else {
+                        neighbours.iter().map(|e| positions[*e] as f32).sum::<f32>()
+                            / neighbours.len() as f32
+                    }
+                })
+                .collect();
+            let mut keyed: Vec<(f32, usize)> = keys
+                .into_iter()
+                .zip(self.layers[l].iter().copied())
+                .collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            self.layers[l] = keyed.into_iter().map(|(_, v)| v).collect();
+            for (i, &vertex) in self.layers[l].iter().enumerate() {
+                positions[vertex] = i;
+            }
+        }
+    }
+
+    /// Runs up to `sweeps` alternating sweeps and keeps the order with the fewest crossings.
+    fn minimize_crossings(&mut self, sweeps: usize) -> usize {
+        let mut best = (self.crossings(), self.layers.clone());
+        let mut stalled = 0;
+        for sweep in 0..sweeps {
+            if best.0 == 0 || stalled >= STALLED_SWEEPS {
+                break;
+            }
+            self.sweep(sweep % 2 == 0);
+            let crossings = self.crossings();
+            if crossings < best.0 {
+                best = (crossings, self.layers.clone());
+                stalled = 0;
+            } else {
+                stalled += 1;
+            }
+        }
+        self.layers = best.1;
+        best.0
+    }
+
+    fn gap(&self, a: usize, b: usize, spacing: f32) -> f32 {
+        self.radii[a] + self.radii[b] + spacing
+    }
+
+    /// Moves the vertices of a layer as close to `desired` as their order and spacing allow.
+    fn place(&self, layer: &[usize], desired: &[f32], spacing: f32, x: &mut [f32]) {
+        let mut left = desired.to_vec();
+        for i in 1..layer.len() {
+            left[i] = left[i].max(left[i - 1] + self.gap(layer[i - 1], layer[i], spacing));
+        }
+        let mut right = desired.to_vec();
+        for i in (0..layer.len().saturating_sub(1)).rev() {
+            right[i] = right[i].min(right[i + 1] - self.gap(layer[i], layer[i + 1], spacing));
+        }
+        for (i, &vertex) in layer.iter().enumerate() {
+            x[vertex] = (left[i] + right[i]) / 2.0;
+        }
+    }
+
+    fn coordinates(&self, spacing: f32) -> Vec<f32> {
+        let mut x = vec![0.0; self.radii.len()];
+        for layer in &self.layers {
+            let mut offset = 0.0;
+            for (i, &vertex) in layer.iter().enumerate() {
+                if i > 0 {
+                    offset += self.gap(layer[i - 1], vertex, spacing);
+                }
+                x[vertex] = offset;
+            }
+            layer.iter().for_each(|e| x[*e] -= offset / 2.0);
+        }
+        for pass in 0..COORDINATE_PASSES {
+            let downwards = pass % 2 == 0;
+            let reference = if downwards { &self.up } else { &self.down };
+            let order: Vec<&Vec<usize>> = if downwards {
+                self.layers.iter().collect()
+            } else {
+                self.layers.iter().rev().collect()
+            };
+            for layer in order {
+                let desired: Vec<f32> = layer
+                    .iter()
+                    .map(|&v| {
+                        let neighbours = &reference[v];
+                        if neighbours.is_empty() {
+                            x[v]
+                        } else {
+                            neighbours.iter().map(|e| x[*e]).sum::<f32>() / neighbours.len() as f32
+                        }
+                    })
+                    .collect();
+                self.place(layer, &desired, spacing, &mut x);
+            }
+        }
+        x
+    }
+}
+
+impl LayoutEngine for Layered {
+    /// Runs at most `iterations` crossing minimization sweeps and returns the remaining number of
+    /// crossings between adjacent layers.
+    fn run(&self, iterations: usize) -> std::io::Result<f32> {
+        let edges = self.edges();
+        let reversed = feedback_edges(self.nodes.len(), &edges);
+        let acyclic: Vec<(usize, usize)> = edges
+            .iter()
+            .zip(&reversed)
+            .map(|(&(from, to), &r)| if r { (to, from) } else { (from, to) })
+            .collect();
+        let proper: Vec<(usize, usize)> = acyclic.iter().copied().filter(|(a, b)| a != b).collect();
+        let layer_of = assign_layers(self.nodes.len(), &proper);
+        let radii: Vec<f32> = self.nodes.iter().map(|e| e.radius()).collect();
+
+        let (mut hierarchy, chains) = Hierarchy::new(&layer_of, &radii, &acyclic);
+        let crossings = hierarchy.minimize_crossings(iterations);
+        let x = hierarchy.coordinates(self.node_spacing);
+        let mut y = vec![0.0; x.len()];
+        for (l, layer) in hierarchy.layers.iter().enumerate() {
+            layer
+                .iter()
+                .for_each(|e| y[*e] = l as f32 * self.layer_spacing);
+        }
+
+        let positions: Vec<(f32, f32)> = (0..self.nodes.len()).map(|i| (x[i], y[i])).collect();
+        write_positions(&self.nodes, &positions);
+        *self.bends.write().unwrap() = chains
+            .iter()
+            .zip(&reversed)
+            .map(|(chain, &r)| {
+                let inner = &chain[1.min(chain.len())..chain.len().saturating_sub(1)];
+                let mut bends: Vec<(f32, f32)> = inner.iter().map(|e| (x[*e], y[*e])).collect();
+                if r {
+                    bends.reverse();
+                }
+                bends
+            })
+            .collect();
+        Ok(crossings as f32)
+    }
+
+    fn nodes(&self) -> &[Arc<Node>] {
+        &self.nodes
+    }
+
+    fn relations(&self) -> &[Arc<Relation>] {
+        &self.relations
+    }
+
+    fn layout(&self) -> Layout {
+        let mut layout = Layout::from_graph(&self.nodes, &self.relations);
+        let bends = self.bends.read().unwrap();
+        layout
+            .edges
+            .iter_mut()
+            .zip(bends.iter())
+            .for_each(|(edge, bends)| edge.bends = bends.clone());
+        layout
+    }
+
+    fn renderer(&self) -> Renderer {
+        let mut renderer = Renderer::new();
+        self.nodes
+            .iter()
+            .enumerate()
+            .for_each(|(i, e)| renderer.add_part(Part::Node(i), Element::from(e.as_ref())));
+        let index = node_index(&self.nodes);
+        let bends = self.bends.read().unwrap();
+        for (i, relation) in self.relations.iter().enumerate() {
+            let part = Part::Edge(index[&relation.from.id()], index[&relation.to.id()]);
+            match bends.get(i) {
+                Some(bends) if !bends.is_empty() => {
+                    let from = *relation.from.loc.read().unwrap();
+                    let to = *relation.to.loc.read().unwrap();
+                    let mut points = vec![(from.x, from.y)];
+                    points.extend(bends);
+                    points.push((to.x, to.y));
+                    renderer.add_part(part, Element::Polyline { points });
+                }
+                _ => renderer.add_part(part, Element::from(relation.as_ref())),
+            }
+        }
+        renderer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{builder::GraphBuilder, layout::LayoutEngine};
+
+    use super::Layered;
+
+    fn graph(n: usize, edges: &[(usize, usize)]) -> GraphBuilder {
+        let mut builder = GraphBuilder::new();
+        for i in 0..n {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        for &(from, to) in edges {
+            builder.add_edge(from, to, 1.0).unwrap();
+        }
+        builder
+    }
+
+    #[test]
+    fn long_edges_bend_on_every_layer() {
+        let engine = Layered::new(graph(3, &[(0, 1), (1, 2), (0, 2)]).build());
+        assert_eq!(engine.run(10).unwrap(), 0.0);
+        let layout = engine.layout();
+        let ys: Vec<f32> = layout.nodes.iter().map(|e| e.y).collect();
+        assert_eq!(ys, vec![0.0, 100.0, 200.0]);
+        assert!(layout.edges[0].bends.is_empty());
+        assert!(layout.edges[1].bends.is_empty());
+        assert_eq!(layout.edges[2].bends.len(), 1);
+        assert_eq!(layout.edges[2].bends[0].1, 100.0);
+        assert!(engine.render(100.0, 100.0).contains("<polyline"));
+    }
+
+    #[test]
+    fn breaks_cycles() {
+        let engine = Layered::new(graph(3, &[(0, 1), (1, 2), (2, 0)]).build());
+        engine.run(10).unwrap();
+        let layout = engine.layout();
+        let mut ys: Vec<f32> = layout.nodes.iter().map(|e| e.y).collect();
+        ys.sort_by(f32::total_cmp);
+        assert_eq!(ys, vec![0.0, 100.0, 200.0]);
+        // The reversed edge still runs from its own start to its own end.
+        let back = &layout.edges[2];
+        assert_eq!(back.bends.len(), 1);
+    }
+
+    #[test]
+    fn removes_crossings() {
+        let engine = Layered::new(graph(4, &[(0, 3), (1, 2)]).build());
+        assert_eq!(engine.run(10).unwrap(), 0.0);
+        let layout = engine.layout();
+        let (a, b) = (&layout.nodes, &layout.edges);
+        assert!(b.iter().all(|e| e.bends.is_empty()));
+        assert_eq!(a[0].x < a[1].x, a[3].x < a[2].x);
+    }
+
+    #[test]
+    fn spaces_nodes_by_their_radius() {
+        let graph = graph(2, &[]).build();
+        graph.nodes[0].set_radius(Some(30.0));
+        let engine = Layered::new(graph);
+        engine.run(10).unwrap();
+        let layout = engine.layout();
+        // The drawn radii 30 and 1 plus the spacing of 50.
+        assert_eq!((layout.nodes[0].x - layout.nodes[1].x).abs(), 81.0);
+    }
+}
@@ -0,0 +1,115 @@
+//! Layout algorithms besides the force simulation in [`sim`](crate::sim).
+//!
+//! All of them write their result into the positions of the [`Node`]s, so the renderers and
+//! exporters work the same regardless of the algorithm.
+
+use std::sync::Arc;
+
+use nohash_hasher::IntMap;
+
+use crate::{
+    adjacency::Adjacency,
+    export::Layout,
+    model::{Coordinates, Node, Relation},
+    render::{Element, Part, Renderer},
+    sim::map_ranges,
+};
+
+pub mod circular;
+pub mod kamada_kawai;
+pub mod layered;
+pub mod multilevel;
+pub mod radial;
+pub mod stress;
+
+/// An algorithm laying out the graph it was created with.
+pub trait LayoutEngine {
+    /// Runs at most `iterations` rounds of the algorithm and moves the nodes accordingly.
+    ///
+    /// Returns the measure the algorithm minimizes, for example the stress or the summed
+    /// displacement of the last steps.
+    fn run(&self, iterations: usize) -> std::io::Result<f32>;
+
+    fn nodes(&self) -> &[Arc<Node>];
+
+    fn relations(&self) -> &[Arc<Relation>];
+
+    fn layout(&self) -> Layout {
+        Layout::from_graph(self.nodes(), self.relations())
+    }
+
+    /// Elements drawing the current layout, labels can be added before rendering.
+    fn renderer(&self) -> Renderer {
+        let mut renderer = Renderer::new();
+        self.nodes()
+            .iter()
+            .enumerate()
+            .for_each(|(i, e)| renderer.add_part(Part::Node(i), Element::from(e.as_ref())));
+        let index = node_index(self.nodes());
+        for relation in self.relations() {
+            let part = Part::Edge(index[&relation.from.id()], index[&relation.to.id()]);
+            renderer.add_part(part, Element::from(relation.as_ref()));
+        }
+        renderer
+    }
+
+    fn render(&self, x: f32, y: f32) -> String {
+        self.renderer().render(x, y)
+    }
+}
+
+/// Maps node ids to their position in `nodes`.
+pub(crate) fn node_index(nodes: &[Arc<Node>]) -> IntMap<usize, usize> {
+    nodes.iter().enumerate().map(|(i, e)| (e.id(), i)).collect()
+}
+
+pub(crate) fn read_positions(nodes: &[Arc<Node>]) -> Vec<(f32, f32)> {
+    nodes
+        .iter()
+        .map(|e| {
+            let loc = *e.loc.read().unwrap();
+            (loc.x, loc.y)
+        })
+        .collect()
+}
+
+pub(crate) fn write_positions(nodes: &[Arc<Node>], positions: &[(f32, f32)]) {
+    nodes
+        .iter()
+        .zip(positions)
+        .for_each(|(n, &(x, y))| n.update_coordinates(Coordinates { x, y }));
+}
+
+/// Replaces infinite distances between components by the largest finite one.
+pub(crate) fn bounded(distance: f32, fallback: f32) -> f32 {
+    if distance.is_finite() {
+        distance
+    } else {
+        fallback
+    }
+}
+
+pub(crate) fn largest_finite<'a, I: IntoIterator<Item = &'a f32>>(distances: I) -> f32 {
+    distances
+        .into_iter()
+        .copied()
+        .filter(|e| e.is_finite())
+        .fold(1.0, f32::max)
+}
+
+/// All pairs shortest path distances, with unreachable pairs set to the largest finite distance.
+pub(crate) fn distance_matrix(adjacency: &Adjacency) -> Vec<Vec<f32>> {
+    let mut rows: Vec<Vec<f32>> = map_ranges(adjacency.len(), |range| {
+        range
+            .map(|source| adjacency.distances(source))
+            .collect::<Vec<_>>()
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+    let fallback = largest_finite(rows.iter().flatten());
+    rows.iter_mut()
+        .flatten()
+        .for_each(|e| *e = bounded(*e, fallback));
+    rows
+}
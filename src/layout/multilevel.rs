@@ -0,0 +1,294 @@
+use std::sync::Arc;
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::{
+    adjacency::Adjacency,
+    builder::GraphBuilder,
+    model::{Graph, Node, Relation},
+    sim::{LayoutParams, SimulationState},
+};
+
+use super::{read_positions, write_positions, LayoutEngine};
+
+/// One graph of the coarsening hierarchy, nodes are addressed by index.
+#[derive(Clone, Debug)]
+struct Level {
+    weights: Vec<f32>,
+    edges: Vec<(usize, usize, f32)>,
+}
+
+/// Multilevel force directed layout in the spirit of FM³ and sfdp.
+///
+/// The graph is repeatedly coarsened by collapsing a matching of heavy edges. The coarsest graph
+/// is laid out with the force simulation, then every finer level starts from the positions of
+/// its coarse nodes and is refined with a shorter simulation. This avoids many of the poor local
+/// minima a simulation of a large graph from random positions ends up in.
+pub struct Multilevel {
+    nodes: Vec<Arc<Node>>,
+    relations: Vec<Arc<Relation>>,
+    params: LayoutParams,
+    /// Coarsening stops once a level has at most this many nodes.
+    pub coarsest: usize,
+    /// Share of the iterations spent refining each level finer than the coarsest one.
+    pub refinement: f32,
+}
+
+impl Multilevel {
+    pub fn new(graph: Graph, params: LayoutParams) -> Self {
+        Self {
+            nodes: graph.nodes,
+            relations: graph.relations,
+            params,
+            coarsest: 50,
+            refinement: 0.25,
+        }
+    }
+
+    fn finest_level(&self) -> Level {
+        let adjacency = Adjacency::from_graph(&self.nodes, &self.relations);
+        let edges = (0..adjacency.len())
+            .flat_map(|from| {
+                adjacency
+                    .neighbours(from)
+                    .iter()
+                    .filter(move |(to, _)| from < *to)
+                    .map(move |&(to, weight)| (from, to, weight))
+            })
+            .collect();
+        Level {
+            weights: self.nodes.iter().map(|e| e.weight).collect(),
+            edges,
+        }
+    }
+
+    fn simulate(
+        &self,
+        level: &Level,
+        positions: &[(f32, f32)],
+        steps: usize,
+    ) -> std::io::Result<Vec<(f32, f32)>> {
+        let mut builder = GraphBuilder::new();
+        for (i, (&weight, &(x, y))) in level.weights.iter().zip(positions).enumerate() {
+            builder
+                .add_node_at(i, x, y, weight)
+                .expect("Level nodes are unique");
+        }
+        for &(from, to, weight) in &level.edges {
+            builder
+                .add_edge(from, to, weight)
+                .expect("Level edges connect known nodes");
+        }
+        let state = SimulationState::from_graph(builder.build(), self.params);
+        state.run_n_steps(steps)?;
+        Ok(read_positions(state.nodes()))
+    }
+}
+
+/// Collapses a heavy edge matching, returning the coarse level and the coarse node of every node.
+fn coarsen(level: &Level) -> (Level, Vec<usize>) {
+    let n = level.weights.len();
+    let adjacency = Adjacency::from_edges(n, level.edges.iter().copied());
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by_key(|e| adjacency.degree(*e));
+
+    let mut parent = vec![usize::MAX; n];
+    let mut weights = Vec::new();
+    for node in order {
+        if parent[node] != usize::MAX {
+            continue;
+        }
+        let partner = adjacency
+            .neighbours(node)
+            .iter()
+            .filter(|(other, _)| *other != node && parent[*other] == usize::MAX)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(other, _)| *other);
+        parent[node] = weights.len();
+        let mut weight = level.weights[node];
+        if let Some(partner) = partner {
+            parent[partner] = weights.len();
+            weight += level.weights[partner];
+        }
+        weights.push(weight);
+    }
+
+    // Parallel edges between coarse nodes are merged into one stronger edge.
+    let mut edges: Vec<(usize, usize, f32)> = level
+        .edges
+        .iter()
+        .map(|&(from, to, weight)| {
+            let (a, b) = (parent[from], parent[to]);
+            (a.min(b), a.max(b), weight)
+        })
+        .filter(|(a, b, _)| a != b)
+        .collect();
+    edges.sort_by_key(|&(a, b, _)| (a, b));
+    let mut merged: Vec<(usize, usize, f32)> = Vec::with_capacity(edges.len());
+    for (a, b, weight) in edges {
+        match merged.last_mut() {
+            Some(last) if last.0 == a && last.1 == b => last.2 += weight,
+            _ => merged.push((a, b, weight)),
+        }
+    }
+
+    (
+        Level {
+            weights,
+            edges: merged,
+        },
+        parent,
+    )
+}
+
+impl LayoutEngine for Multilevel {
+    /// Runs `iterations` steps on the coarsest level and a share of them on every finer level.
+    /// Returns the summed displacement of the final refinement.
+    fn run(&self, iterations: usize) -> std::io::Result<f32> {
+        let mut levels = Vec::from([self.finest_level()]);
+        let mut parents: Vec<Vec<usize>> = Vec::new();
+        loop {
+            let current = levels.last().unwrap();
+            if current.weights.len() <= self.coarsest {
+                break;
+            }
+            let (coarse, parent) = coarsen(current);
+            // Stop once matching barely shrinks the graph, as for stars.
+            if coarse.weights.len() as f32 > 0.9 * current.weights.len() as f32 {
+                break;
+            }
+            levels.push(coarse);
+            parents.push(parent);
+        }
+
+        // The coarsest level starts from the positions of one of its members.
+        let mut positions = read_positions(&self.nodes);
+        for parent in &parents {
+            let mut coarse = vec![(0.0, 0.0); parent.iter().max().map_or(0, |e| e + 1)];
+            for (node, &p) in parent.iter().enumerate() {
+                coarse[p] = positions[node];
+            }
+            positions = coarse;
+        }
+
+        let refine_steps = ((iterations as f32 * self.refinement) as usize).max(1);
+        let mut rng = SmallRng::from_seed([0u8; 32]);
+        positions = self.simulate(levels.last().unwrap(), &positions, iterations)?;
+        for (depth, parent) in parents.iter().enumerate().rev() {
+            let fine = &levels[depth];
+            let spread = mean_edge_length(&levels[depth + 1], &positions) * 0.1;
+            positions = parent
+                .iter()
+                .map(|&p| {
+                    let (x, y) = positions[p];
+                    (
+                        x + rng.gen_range(-1.0..=1.0) * spread,
+                        y + rng.gen_range(-1.0..=1.0) * spread,
+                    )
+                })
+                .collect();
+            if depth > 0 {
+                positions = self.simulate(fine, &positions, refine_steps)?;
+            }
+        }
+
+        write_positions(&self.nodes, &positions);
+        let finest_steps = if parents.is_empty() {
+            iterations
+        } else {
+            refine_steps
+        };
+        let state = SimulationState::from_graph(
+            Graph::new(self.nodes.clone(), self.relations.clone()),
+            self.params,
+        );
+        state.run_n_steps(finest_steps)
+    }
+
+    fn nodes(&self) -> &[Arc<Node>] {
+        &self.nodes
+    }
+
+    fn relations(&self) -> &[Arc<Relation>] {
+        &self.relations
+    }
+}
+
+fn mean_edge_length(level: &Level, positions: &[(f32, f32)]) -> f32 {
+    if level.edges.is_empty() {
+        return 1.0;
+    }
+    level
+        .edges
+        .iter()
+        .map(|&(a, b, _)| (positions[a].0 - positions[b].0).hypot(positions[a].1 - positions[b].1))
+        .sum::<f32>()
+        / level.edges.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        adjacency::Adjacency,
+        builder::GraphBuilder,
+        layout::LayoutEngine,
+        metrics::{edge_crossings, stress},
+        sim::{LayoutParams, SimulationState},
+    };
+
+    use super::{coarsen, Level, Multilevel};
+
+    #[test]
+    fn coarsening_halves_path() {
+        let level = Level {
+            weights: vec![1.0; 8],
+            edges: (0..7).map(|i| (i, i + 1, 1.0)).collect(),
+        };
+        let (coarse, parent) = coarsen(&level);
+        assert_eq!(coarse.weights.len(), 4);
+        assert_eq!(coarse.weights.iter().sum::<f32>(), 8.0);
+        assert_eq!(coarse.edges.len(), 3);
+        assert!(parent.iter().all(|e| *e < 4));
+    }
+
+    #[test]
+    fn lays_out_grid() {
+        let k = 12;
+        let grid = || {
+            let mut builder = GraphBuilder::new();
+            for i in 0..k * k {
+                builder.add_node(i, 1.0).unwrap();
+            }
+            for r in 0..k {
+                for c in 0..k {
+                    let i = r * k + c;
+                    if c + 1 < k {
+                        builder.add_edge(i, i + 1, 1.0).unwrap();
+                    }
+                    if r + 1 < k {
+                        builder.add_edge(i, i + k, 1.0).unwrap();
+                    }
+                }
+            }
+            builder.build()
+        };
+        let mut engine = Multilevel::new(grid(), LayoutParams::default());
+        engine.coarsest = 10;
+        engine.run(200).unwrap();
+        let layout = engine.layout();
+        // A plain simulation from the same random start, with the same number of steps.
+        let plain = SimulationState::from_graph(grid(), LayoutParams::default());
+        plain.run_n_steps(200).unwrap();
+        let plain = plain.layout();
+        let adjacency = Adjacency::from_layout(&layout);
+        // The coarse levels unfold the grid before the details are placed, the plain simulation
+        // keeps the twists of the start.
+        let (crossings, plain_crossings) = (edge_crossings(&layout), edge_crossings(&plain));
+        assert!(
+            crossings < plain_crossings,
+            "{crossings} >= {plain_crossings}"
+        );
+        let (stress, plain_stress) = (stress(&layout, &adjacency), stress(&plain, &adjacency));
+        assert!(stress < plain_stress, "{stress} >= {plain_stress}");
+    }
+}
@@ -0,0 +1,185 @@
+use std::{collections::VecDeque, f32::consts::PI, sync::Arc};
+
+use crate::{
+    adjacency::Adjacency,
+    builder::{GraphError, INITIAL_EXTENT},
+    model::{Graph, Node, Relation},
+};
+
+use super::{write_positions, LayoutEngine};
+
+/// Radial tree layout.
+///
+/// A breadth first search from the root builds a spanning tree. Every node sits on the ring of
+/// its depth, and the children of a node share its wedge of the ring in proportion to the
+/// number of leaves below them, so subtrees never overlap. Nodes the root cannot reach are
+/// spread evenly on one more ring outside the tree.
+pub struct Radial {
+    nodes: Vec<Arc<Node>>,
+    relations: Vec<Arc<Relation>>,
+    /// Id of the node in the centre, `None` picks the node with the highest degree.
+    pub root: Option<usize>,
+    /// Radius of the outermost ring, by default the same area random placement covers.
+    pub radius: f32,
+}
+
+impl Radial {
+    pub fn new(graph: Graph, root: Option<usize>) -> Self {
+        Self {
+            nodes: graph.nodes,
+            relations: graph.relations,
+            root,
+            radius: INITIAL_EXTENT / 2.0,
+        }
+    }
+
+    fn root_index(&self, adjacency: &Adjacency) -> Result<Option<usize>, GraphError> {
+        match self.root {
+            Some(id) => self
+                .nodes
+                .iter()
+                .position(|e| e.id() == id)
+                .map(Some)
+                .ok_or(GraphError::UnknownNode(id)),
+            None => Ok((0..adjacency.len()).max_by_key(|e| (adjacency.degree(*e), usize::MAX - e))),
+        }
+    }
+}
+
+/// Breadth first spanning tree, returning the visiting order and the children of every node.
+fn spanning_tree(adjacency: &Adjacency, root: usize) -> (Vec<usize>, Vec<Vec<usize>>) {
+    let mut children = vec![Vec::new(); adjacency.len()];
+    let mut visited = vec![false; adjacency.len()];
+    let mut order = Vec::new();
+    let mut queue = VecDeque::from([root]);
+    visited[root] = true;
+    while let Some(node) = queue.pop_front() {
+        order.push(node);
+        for &(neighbour, _) in adjacency.neighbours(node) {
+            if !visited[neighbour] {
+                visited[neighbour] = true;
+                children[node].push(neighbour);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    (order, children)
+}
+
+impl LayoutEngine for Radial {
+    /// Lays out the tree in one go regardless of `iterations` and returns its depth.
+    fn run(&self, _iterations: usize) -> std::io::Result<f32> {
+        let adjacency = Adjacency::from_graph(&self.nodes, &self.relations);
+        let Some(root) = self.root_index(&adjacency)? else {
+            return Ok(0.0);
+        };
+        let (order, children) = spanning_tree(&adjacency, root);
+
+        let n = adjacency.len();
+        let mut depth = vec![0; n];
+        for &node in &order {
+            for &child in &children[node] {
+                depth[child] = depth[node] + 1;
+            }
+        }
+        let mut leaves = vec![1; n];
+        for &node in order.iter().rev() {
+            if !children[node].is_empty() {
+                leaves[node] = children[node].iter().map(|e| leaves[*e]).sum();
+            }
+        }
+
+        // Every node owns the wedge starting at `start` and spanning `span` radians.
+        let mut start = vec![0.0; n];
+        let mut span = vec![0.0; n];
+        span[root] = 2.0 * PI;
+        let mut angle = vec![0.0; n];
+        for &node in &order {
+            angle[node] = start[node] + span[node] / 2.0;
+            let mut next = start[node];
+            for &child in &children[node] {
+                start[child] = next;
+                span[child] = span[node] * leaves[child] as f32 / leaves[node] as f32;
+                next += span[child];
+            }
+        }
+
+        let mut rings = order.iter().map(|e| depth[*e]).max().unwrap_or(0);
+        let unreachable: Vec<usize> = (0..n).filter(|e| *e != root && depth[*e] == 0).collect();
+        if !unreachable.is_empty() {
+            rings += 1;
+            for (i, &node) in unreachable.iter().enumerate() {
+                depth[node] = rings;
+                angle[node] = 2.0 * PI * i as f32 / unreachable.len() as f32;
+            }
+        }
+        let spacing = self.radius / rings.max(1) as f32;
+        let positions: Vec<(f32, f32)> = (0..n)
+            .map(|node| {
+                let distance = spacing * depth[node] as f32;
+                (
+                    self.radius + distance * angle[node].cos(),
+                    self.radius + distance * angle[node].sin(),
+                )
+            })
+            .collect();
+        write_positions(&self.nodes, &positions);
+        Ok(order.iter().map(|e| depth[*e]).max().unwrap_or(0) as f32)
+    }
+
+    fn nodes(&self) -> &[Arc<Node>] {
+        &self.nodes
+    }
+
+    fn relations(&self) -> &[Arc<Relation>] {
+        &self.relations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{builder::GraphBuilder, layout::LayoutEngine};
+
+    use super::Radial;
+
+    #[test]
+    fn rings_by_depth() {
+        let mut builder = GraphBuilder::new();
+        for i in 0..6 {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        builder
+            .add_edge(0, 1, 1.0)
+            .unwrap()
+            .add_edge(0, 2, 1.0)
+            .unwrap()
+            .add_edge(1, 3, 1.0)
+            .unwrap()
+            .add_edge(1, 4, 1.0)
+            .unwrap();
+        let engine = Radial::new(builder.build(), Some(0));
+        assert_eq!(engine.run(1).unwrap(), 2.0);
+
+        let layout = engine.layout();
+        let distance = |i: usize| {
+            let node = &layout.nodes[i];
+            (node.x - engine.radius).hypot(node.y - engine.radius)
+        };
+        // Two rings of the tree and one for the unreachable node 5.
+        let ring = engine.radius / 3.0;
+        assert!(distance(0) < 1.0E-3);
+        for (node, depth) in [(1, 1.0), (2, 1.0), (3, 2.0), (4, 2.0), (5, 3.0)] {
+            assert!(
+                (distance(node) - ring * depth).abs() < 1.0E-3,
+                "Node {node}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_root() {
+        let mut builder = GraphBuilder::new();
+        builder.add_node(0, 1.0).unwrap();
+        assert!(Radial::new(builder.build(), Some(7)).run(1).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use std::sync::Arc;
+
+use crate::{
+    adjacency::Adjacency,
+    model::{Graph, Node, Relation},
+    sim::map_ranges,
+};
+
+use super::{
+    bounded, distance_matrix, largest_finite, read_positions, write_positions, LayoutEngine,
+};
+
+/// Largest number of nodes laid out by the full variant unless pivots are given. Its distance
+/// matrix takes 16 MB at this size and grows quadratically.
+pub const FULL_LIMIT: usize = 2000;
+
+/// Number of pivots of the sparse variant chosen for graphs above [`FULL_LIMIT`].
+pub const AUTO_PIVOTS: usize = 200;
+
+/// Target distance and weight of one term of the stress function.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Term {
+    pub(crate) other: usize,
+    pub(crate) distance: f32,
+    pub(crate) weight: f32,
+}
+
+/// Stress majorization (SMACOF) layout.
+///
+/// Places the nodes so that their euclidean distances match the weighted shortest path
+/// distances of the graph. The full variant considers every pair of nodes and needs quadratic
+/// memory. The sparse variant only considers graph neighbours and a set of pivot nodes standing
+/// in for the rest, following Ortmann, Klimenta and Brandes, and is suited for large graphs.
+pub struct StressMajorization {
+    nodes: Vec<Arc<Node>>,
+    relations: Vec<Arc<Relation>>,
+    /// Stops once the relative improvement of the stress drops below this value.
+    pub tolerance: f32,
+    /// Number of pivots of the sparse variant, `None` for the full variant up to [`FULL_LIMIT`]
+    /// nodes and [`AUTO_PIVOTS`] pivots above.
+    pub pivots: Option<usize>,
+}
+
+impl StressMajorization {
+    pub fn new(graph: Graph) -> Self {
+        Self {
+            nodes: graph.nodes,
+            relations: graph.relations,
+            tolerance: 1.0E-4,
+            pivots: None,
+        }
+    }
+
+    pub fn with_pivots(mut self, pivots: Option<usize>) -> Self {
+        self.pivots = pivots.map(|e| e.max(1));
+        self
+    }
+
+    /// Number of pivots used by [`LayoutEngine::run`], `None` for the full variant.
+    pub fn pivot_count(&self) -> Option<usize> {
+        self.pivots
+            .or_else(|| (self.nodes.len() > FULL_LIMIT).then_some(AUTO_PIVOTS))
+    }
+}
+
+impl LayoutEngine for StressMajorization {
+    /// Runs at most `iterations` majorization rounds and returns the final stress.
+    fn run(&self, iterations: usize) -> std::io::Result<f32> {
+        let adjacency = Adjacency::from_graph(&self.nodes, &self.relations);
+        let terms = match self.pivot_count() {
+            None => full_terms(&adjacency),
+            Some(pivots) => sparse_terms(&adjacency, pivots),
+        };
+        let mut positions = read_positions(&self.nodes);
+        let stress = majorize(&mut positions, &terms, iterations, self.tolerance);
+        write_positions(&self.nodes, &positions);
+        Ok(stress)
+    }
+
+    fn nodes(&self) -> &[Arc<Node>] {
+        &self.nodes
+    }
+
+    fn relations(&self) -> &[Arc<Relation>] {
+        &self.relations
+    }
+}
+
+fn full_terms(adjacency: &Adjacency) -> Vec<Vec<Term>> {
+    distance_matrix(adjacency)
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|(j, d)| *j != i && **d > 0.0)
+                .map(|(j, &d)| Term {
+                    other: j,
+                    distance: d,
+                    weight: d.powi(-2),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Picks pivots by repeatedly taking the node farthest away from all pivots chosen so far.
+fn max_min_pivots(adjacency: &Adjacency, count: usize) -> Vec<(usize, Vec<f32>)> {
+    let mut pivots: Vec<(usize, Vec<f32>)> = Vec::new();
+    let mut nearest = vec![f32::INFINITY; adjacency.len()];
+    let mut next = 0;
+    while pivots.len() < count.min(adjacency.len()) {
+        let distances = adjacency.distances(next);
+        nearest
+            .iter_mut()
+            .zip(&distances)
+            .for_each(|(n, d)| *n = n.min(*d));
+        pivots.push((next, distances));
+        next = nearest
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap();
+    }
+    pivots
+}
+
+fn sparse_terms(adjacency: &Adjacency, pivot_count: usize) -> Vec<Vec<Term>> {
+    let pivots = max_min_pivots(adjacency, pivot_count);
+    if pivots.is_empty() {
+        return Vec::new();
+    }
+    let fallback = largest_finite(pivots.iter().flat_map(|(_, e)| e));
+
+    // Every node belongs to the region of its closest pivot.
+    let mut regions: Vec<Vec<f32>> = vec![Vec::new(); pivots.len()];
+    for node in 0..adjacency.len() {
+        let (closest, distance) = pivots
+            .iter()
+            .enumerate()
+            .map(|(p, (_, distances))| (p, distances[node]))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        regions[closest].push(distance);
+    }
+    regions.iter_mut().for_each(|e| e.sort_by(f32::total_cmp));
+
+    (0..adjacency.len())
+        .map(|i| {
+            let mut terms: Vec<Term> = adjacency
+                .neighbours(i)
+                .iter()
+                .filter(|(j, d)| *j != i && *d > 0.0)
+                .map(|&(j, d)| Term {
+                    other: j,
+                    distance: d,
+                    weight: d.powi(-2),
+                })
+                .collect();
+            for ((pivot, distances), region) in pivots.iter().zip(&regions) {
+                let pivot = *pivot;
+                if pivot == i || terms.iter().any(|e| e.other == pivot) {
+                    continue;
+                }
+                let d = bounded(distances[i], fallback);
+                if d <= 0.0 {
+                    continue;
+                }
+                // The pivot stands in for the part of its region that is closer to it than to i.
+                let represented = region.partition_point(|e| *e <= d / 2.0).max(1);
+                terms.push(Term {
+                    other: pivot,
+                    distance: d,
+                    weight: represented as f32 * d.powi(-2),
+                });
+            }
+            terms
+        })
+        .collect()
+}
+
+fn stress(positions: &[(f32, f32)], terms: &[Vec<Term>]) -> f32 {
+    terms
+        .iter()
+        .enumerate()
+        .flat_map(|(i, row)| row.iter().map(move |t| (i, t)))
+        .map(|(i, t)| {
+            let (a, b) = (positions[i], positions[t.other]);
+            t.weight * ((a.0 - b.0).hypot(a.1 - b.1) - t.distance).powi(2)
+        })
+        .sum()
+}
+
+pub(crate) fn majorize(
+    positions: &mut Vec<(f32, f32)>,
+    terms: &[Vec<Term>],
+    iterations: usize,
+    tolerance: f32,
+) -> f32 {
+    let mut current = stress(positions, terms);
+    for _ in 0..iterations {
+        let previous: &Vec<(f32, f32)> = positions;
+        let next: Vec<(f32, f32)> = map_ranges(previous.len(), |range| {
+            range
+                .map(|i| {
+                    let (x, y) = previous[i];
+                    let (mut sx, mut sy, mut sw) = (0.0, 0.0, 0.0);
+                    for t in &terms[i] {
+                        let (ox, oy) = previous[t.other];
+                        let length = (x - ox).hypot(y - oy);
+                        let (dx, dy) = if length > f32::EPSILON {
+                            ((x - ox) / length, (y - oy) / length)
+                        } else {
+                            (0.0, 0.0)
+                        };
+                        sx += t.weight * (ox + t.distance * dx);
+                        sy += t.weight * (oy + t.distance * dy);
+                        sw += t.weight;
+                    }
+                    if sw > 0.0 {
+                        (sx / sw, sy / sw)
+                    } else {
+                        (x, y)
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect();
+        *positions = next;
+
+        let updated = stress(positions, terms);
+        let improvement = (current - updated) / current.max(f32::EPSILON);
+        current = updated;
+        if improvement.abs() < tolerance {
+            break;
+        }
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{builder::GraphBuilder, layout::LayoutEngine, model::Node};
+
+    use super::{StressMajorization, AUTO_PIVOTS, FULL_LIMIT};
+
+    fn distance(nodes: &[Arc<Node>], a: usize, b: usize) -> f32 {
+        let a = *nodes[a].loc.read().unwrap();
+        let b = *nodes[b].loc.read().unwrap();
+        a.to(b).length()
+    }
+
+    #[test]
+    fn reproduces_path_distances() {
+        let mut builder = GraphBuilder::new();
+        for i in 0..4 {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        builder
+            .add_edge(0, 1, 1.0)
+            .unwrap()
+            .add_edge(1, 2, 2.0)
+            .unwrap()
+            .add_edge(2, 3, 1.0)
+            .unwrap();
+        let engine = StressMajorization::new(builder.build());
+        let stress = engine.run(300).unwrap();
+        let nodes = engine.nodes();
+        assert!(stress < 1.0E-3, "Stress is {stress}");
+        assert!((distance(nodes, 0, 1) - 1.0).abs() < 0.05);
+        assert!((distance(nodes, 1, 2) - 2.0).abs() < 0.05);
+        assert!((distance(nodes, 0, 3) - 4.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn sparse_variant_keeps_neighbours_close() {
+        let mut builder = GraphBuilder::new();
+        let n = 40;
+        for i in 0..n {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        for i in 0..n {
+            builder.add_edge(i, (i + 1) % n, 1.0).unwrap();
+        }
+        let engine = StressMajorization::new(builder.build()).with_pivots(Some(8));
+        engine.run(300).unwrap();
+        let nodes = engine.nodes();
+        for i in 0..n {
+            let d = distance(nodes, i, (i + 1) % n);
+            assert!(d < 2.0, "Edge {i} has length {d}");
+        }
+        assert!(distance(nodes, 0, n / 2) > 5.0);
+    }
+
+    #[test]
+    fn large_graphs_default_to_pivots() {
+        let mut builder = GraphBuilder::new();
+        for i in 0..=FULL_LIMIT {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        let engine = StressMajorization::new(builder.build());
+        assert_eq!(engine.pivot_count(), Some(AUTO_PIVOTS));
+        assert_eq!(engine.with_pivots(Some(10)).pivot_count(), Some(10));
+
+        let small = StressMajorization::new(GraphBuilder::new().build());
+        assert_eq!(small.pivot_count(), None);
+    }
+}
@@ -0,0 +1,44 @@
+//! Force directed graph layout.
+//!
+//! Graphs are either read from CSV files with [`io::read_all`] or assembled with a
+//! [`GraphBuilder`](builder::GraphBuilder):
+//!
+//! ```
+//! use graph_visualizer::{
+//!     builder::GraphBuilder,
+//!     sim::{LayoutParams, SimulationState},
+//! };
+//!
+//! let mut builder = GraphBuilder::new();
+//! builder.add_node(1, 1.0)?.add_node(2, 1.0)?.add_edge(1, 2, 1.0)?;
+//! builder.label(1, "Berlin")?;
+//!
+//! let state = SimulationState::from_graph(builder.build(), LayoutParams::default());
+//! state.run_n_steps(100).unwrap();
+//! let svg = state.render(500.0, 500.0);
+//! assert!(svg.contains("<circle"));
+//! # Ok::<(), graph_visualizer::builder::GraphError>(())
+//! ```
+
+pub mod adjacency;
+pub mod analysis;
+pub mod builder;
+pub mod community;
+pub mod components;
+pub mod export;
+pub mod filter;
+pub mod geo;
+pub mod html;
+pub mod incremental;
+pub mod io;
+pub mod labels;
+pub mod layout;
+pub mod metrics;
+pub mod model;
+pub mod overlap;
+pub mod render;
+pub mod serve;
+pub mod sim;
+pub mod simplify;
+pub mod stream;
+pub mod tune;
@@ -1,33 +1,480 @@
-use std::{error::Error, time::Instant, io::Write};
+use std::{
+    error::Error,
+    io::{BufReader, Write},
+    path::PathBuf,
+    time::Instant,
+};
 
 use clap::Parser;
+use graph_visualizer::{
+    adjacency::Adjacency,
+    analysis::{self, Centrality},
+    builder::GraphError,
+    community, components,
+    export::Layout,
+    filter::Filter,
+    geo::{self, ANCHOR_STRENGTH},
+    html, incremental,
+    io::{read_all, TraceWriter},
+    labels::{self, LabelPlacement},
+    layout::{
+        circular::Circular, kamada_kawai::KamadaKawai, layered::Layered, multilevel::Multilevel,
+        radial::Radial, stress::StressMajorization, LayoutEngine,
+    },
+    metrics::LayoutMetrics,
+    model::Graph,
+    overlap::OverlapRemoval,
+    serve::Server,
+    sim::{LayoutParams, SimulationState},
+    simplify,
+    stream::{self, Stream},
+    tune,
+};
 
-use crate::{io::read_all, sim::SimulationState, cli::Args};
+use crate::cli::{Algorithm, Args, Command, Format, Placement, ReportFormat, RunArgs, StreamArgs};
 
-pub(crate) mod io;
-pub(crate) mod model;
-pub(crate) mod render;
-pub(crate) mod sim;
-pub(crate) mod cli;
+mod cli;
+
+/// Steps between two rows of `--trace` without `--report-every`.
+const TRACE_INTERVAL: usize = 100;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Args = Args::parse();
 
-    let node_file = std::fs::File::open(args.nodes_file).expect("Failed to open nodes file");
-    let relations_file = std::fs::File::open(args.relations_file).expect("Failed to open relations file");
-    let (nodes, relations) = read_all(node_file, relations_file)?;
+    match args.command {
+        Some(Command::Metrics { layout, format }) => metrics(layout, format),
+        Some(Command::Stream(args)) => stream(args),
+        Some(Command::Serve { port, stream }) => serve(port, stream),
+        None => run(args.run),
+    }
+}
+
+fn metrics(path: PathBuf, format: ReportFormat) -> Result<(), Box<dyn Error>> {
+    let layout = Layout::from_json(BufReader::new(std::fs::File::open(path)?))?;
+    let metrics = LayoutMetrics::compute(&layout);
+    match format {
+        ReportFormat::Table => print!("{}", metrics.to_table()),
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&metrics)?),
+    }
+    Ok(())
+}
+
+/// Simulation of the graph the stream and serve commands start with.
+fn initial_stream(args: &StreamArgs) -> Result<Stream, Box<dyn Error>> {
+    let graph = match (&args.nodes_file, &args.relations_file) {
+        (Some(nodes), Some(relations)) => {
+            read_all(std::fs::File::open(nodes)?, std::fs::File::open(relations)?)?
+        }
+        _ => Graph::default(),
+    };
+    let params = LayoutParams {
+        spring_scale: args.spring,
+        coloumb_scale: args.coloumb,
+        time_delta: args.time,
+        gravity_scale: args.gravity,
+        ..LayoutParams::default()
+    };
+    Ok(Stream::new(SimulationState::from_graph(graph, params)))
+}
+
+fn stream(args: StreamArgs) -> Result<(), Box<dyn Error>> {
+    let mut stream = initial_stream(&args)?;
+    stream::run(
+        &mut stream,
+        BufReader::new(std::io::stdin()),
+        std::io::stdout().lock(),
+        args.steps_between,
+    )?;
+    Ok(())
+}
+
+fn serve(port: u16, args: StreamArgs) -> Result<(), Box<dyn Error>> {
+    let server = Server::bind(
+        ("127.0.0.1", port),
+        initial_stream(&args)?,
+        args.steps_between,
+    )?;
+    println!("Serving => http://{}", server.local_addr()?);
+    server.run()?;
+    Ok(())
+}
+
+/// Prints the shortest path between two node ids and returns the positions along it.
+fn shortest_path(
+    engine: &dyn LayoutEngine,
+    from: usize,
+    to: usize,
+) -> Result<Vec<usize>, Box<dyn Error>> {
+    let nodes = engine.nodes();
+    let index = |id: usize| {
+        nodes
+            .iter()
+            .position(|e| e.id() == id)
+            .ok_or(GraphError::UnknownNode(id))
+    };
+    let (source, target) = (index(from)?, index(to)?);
+    let adjacency = Adjacency::from_graph(nodes, engine.relations());
+    let (length, path) = adjacency
+        .path(source, target)
+        .ok_or_else(|| format!("There is no path from {from} to {to}"))?;
+    let hops: Vec<String> = path.iter().map(|e| nodes[*e].id().to_string()).collect();
+    println!(
+        "Path => {} Length => {length} Hops => {}",
+        hops.join(" -> "),
+        path.len() - 1
+    );
+    Ok(path)
+}
+
+/// Computes the centralities asked for and lets the one of `--radius-by` set the node radii.
+fn centralities(graph: &Graph, args: &RunArgs) -> Vec<(Centrality, Vec<f32>)> {
+    let adjacency = Adjacency::from_graph(&graph.nodes, &graph.relations);
+    let mut wanted: Vec<Centrality> = Vec::new();
+    for kind in &args.centrality {
+        if !wanted.contains(&(*kind).into()) {
+            wanted.push((*kind).into());
+        }
+    }
+    let computed: Vec<(Centrality, Vec<f32>)> = wanted
+        .into_iter()
+        .map(|e| (e, e.compute(&adjacency)))
+        .collect();
+    for (centrality, values) in &computed {
+        if let Some((top, value)) = values.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)) {
+            println!(
+                "Centrality => {} Top => {} ({value})",
+                centrality.name(),
+                graph.nodes[top].id()
+            );
+        }
+    }
+    if let Some(kind) = args.radius_by {
+        let centrality: Centrality = kind.into();
+        let radii = match computed.iter().find(|e| e.0 == centrality) {
+            Some((_, values)) => analysis::radii(values),
+            None => analysis::radii(&centrality.compute(&adjacency)),
+        };
+        for (node, radius) in graph.nodes.iter().zip(radii) {
+            node.set_radius(Some(radius));
+        }
+    }
+    computed
+}
 
-    let state = SimulationState::new(nodes, relations, args.spring, args.coloumb, args.time);
+fn run(args: RunArgs) -> Result<(), Box<dyn Error>> {
+    if args.highlight_path.is_some() && args.format == Format::Csv {
+        return Err("A highlighted path cannot be written to csv, use svg, html or json".into());
+    }
+    if args.pack_components && args.algorithm != Algorithm::Force {
+        return Err("Packing components needs the force algorithm".into());
+    }
+    let node_file = std::fs::File::open(&args.nodes_file).expect("Failed to open nodes file");
+    let relations_file =
+        std::fs::File::open(&args.relations_file).expect("Failed to open relations file");
+    let graph = read_all(node_file, relations_file)?;
+    let filter = Filter {
+        predicates: args.predicates.clone(),
+        min_weight: args.min_weight,
+        ego: args.ego.map(|e| (e, args.ego_hops)),
+        min_degree: args.min_degree,
+        largest_component: args.largest_component,
+    };
+    let graph = if filter.is_empty() {
+        graph
+    } else {
+        let filtered = filter.apply(&graph)?;
+        println!(
+            "Filtered => {} of {} nodes {} of {} relations",
+            filtered.nodes.len(),
+            graph.nodes.len(),
+            filtered.relations.len(),
+            graph.relations.len()
+        );
+        filtered
+    };
+    let graph = match &args.collapse {
+        Some(attribute) => {
+            let collapsed = simplify::collapse(&graph, attribute)?;
+            println!(
+                "Collapsed => {} nodes into {}",
+                graph.nodes.len(),
+                collapsed.nodes.len()
+            );
+            collapsed
+        }
+        None => graph,
+    };
+    match args.initial {
+        Placement::Random => {}
+        Placement::Circular => {
+            Circular::new(graph.clone()).run(args.steps)?;
+        }
+        Placement::Radial => {
+            Radial::new(graph.clone(), args.root).run(args.steps)?;
+        }
+    }
+    if let Some(projection) = args.projection {
+        let anchor = match args.algorithm {
+            Algorithm::Geographic => Some(args.anchor.unwrap_or(ANCHOR_STRENGTH)),
+            _ => args.anchor,
+        };
+        let placed = geo::place(&graph.nodes, projection.into(), anchor)?;
+        println!("Projected => {placed} of {} nodes", graph.nodes.len());
+    } else if args.algorithm == Algorithm::Geographic {
+        return Err("The geographic layout needs a --projection".into());
+    }
+    let args = match &args.previous {
+        Some(path) => {
+            if args.algorithm != Algorithm::Force {
+                return Err("An incremental layout needs the force algorithm".into());
+            }
+            let previous = Layout::from_json(BufReader::new(std::fs::File::open(path)?))?;
+            let placed = incremental::place(
+                &graph.nodes,
+                &graph.relations,
+                &previous,
+                Some(args.previous_anchor),
+            );
+            println!("Incremental => {placed}");
+            RunArgs {
+                steps: args.incremental_steps,
+                ..args
+            }
+        }
+        None => args,
+    };
+    if let Some(method) = args.communities {
+        let (count, modularity) = community::assign(&graph.nodes, &graph.relations, method.into());
+        println!("Communities => {count} Modularity => {modularity}");
+    }
+    let centralities = centralities(&graph, &args);
+    let nodes = graph.nodes.clone();
+    let (graph, contraction) = if args.contract_chains {
+        // Only positions survive the expansion, bend points would be lost.
+        if args.algorithm == Algorithm::Layered {
+            return Err("Contracted chains cannot be expanded into a layered layout".into());
+        }
+        let contraction = simplify::contract_chains(&graph)?;
+        println!(
+            "Contracted => {} chains with {} nodes",
+            contraction.chains.len(),
+            contraction.removed()
+        );
+        (contraction.graph.clone(), Some((graph, contraction)))
+    } else {
+        (graph, None)
+    };
 
+    let params = if args.auto_tune {
+        let report = match args.tune_steps {
+            Some(steps) => {
+                let layout = Layout::from_graph(&graph.nodes, &graph.relations);
+                tune::search(&layout, steps, args.tune_objective.into())?
+            }
+            None => tune::tune(&graph.nodes, &graph.relations),
+        };
+        println!("Tuned => {report}");
+        LayoutParams {
+            gravity_scale: args.gravity,
+            collision_scale: args.collision,
+            community_scale: args.community_attraction,
+            ..report.params
+        }
+    } else {
+        LayoutParams {
+            spring_scale: args.spring,
+            coloumb_scale: args.coloumb,
+            time_delta: args.time,
+            gravity_scale: args.gravity,
+            collision_scale: args.collision,
+            community_scale: args.community_attraction,
+        }
+    };
     let start = Instant::now();
-    let last_change = state.run_n_steps(args.steps)?;
-    let elapsed = start.elapsed();
-    println!("Elapsed => {:?} Last Change => {last_change}", elapsed);
+    let engine: Box<dyn LayoutEngine> = match args.algorithm {
+        Algorithm::Force => {
+            let state = SimulationState::from_graph(graph.clone(), params);
+            let last_change = if args.pack_components {
+                components::simulate_separately(&graph, params, args.steps, args.packing.into())?
+            } else {
+                simulate(&state, &args)?
+            };
+            let elapsed = start.elapsed();
+            println!(
+                "Elapsed => {:?} Last Change => {last_change}{}",
+                elapsed,
+                final_energy(&state, &args)
+            );
+            Box::new(state)
+        }
+        Algorithm::Stress => {
+            let engine = StressMajorization::new(graph).with_pivots(args.pivots);
+            if let (None, Some(pivots)) = (args.pivots, engine.pivot_count()) {
+                println!("Pivots => {pivots} for {} nodes", engine.nodes().len());
+            }
+            let stress = engine.run(args.steps)?;
+            let elapsed = start.elapsed();
+            println!("Elapsed => {:?} Stress => {stress}", elapsed);
+            Box::new(engine)
+        }
+        Algorithm::KamadaKawai => {
+            let engine = KamadaKawai::new(graph);
+            let energy = engine.run(args.steps)?;
+            let elapsed = start.elapsed();
+            println!("Elapsed => {:?} Energy => {energy}", elapsed);
+            Box::new(engine)
+        }
+        Algorithm::Layered => {
+            let engine = Layered::new(graph);
+            let crossings = engine.run(args.steps)?;
+            let elapsed = start.elapsed();
+            println!("Elapsed => {:?} Crossings => {crossings}", elapsed);
+            Box::new(engine)
+        }
+        Algorithm::Circular => {
+            let engine = Circular::new(graph);
+            let crossings = engine.run(args.steps)?;
+            let elapsed = start.elapsed();
+            println!("Elapsed => {:?} Crossings => {crossings}", elapsed);
+            Box::new(engine)
+        }
+        Algorithm::Radial => {
+            let engine = Radial::new(graph, args.root);
+            let depth = engine.run(args.steps)?;
+            let elapsed = start.elapsed();
+            println!("Elapsed => {:?} Depth => {depth}", elapsed);
+            Box::new(engine)
+        }
+        Algorithm::Geographic => {
+            // Without springs only the repulsion of close nodes moves them off their anchors.
+            let params = LayoutParams {
+                spring_scale: 0.0,
+                ..params
+            };
+            let state = SimulationState::from_graph(graph, params);
+            let last_change = simulate(&state, &args)?;
+            let elapsed = start.elapsed();
+            println!(
+                "Elapsed => {:?} Last Change => {last_change}{}",
+                elapsed,
+                final_energy(&state, &args)
+            );
+            Box::new(state)
+        }
+        Algorithm::Multilevel => {
+            let engine = Multilevel::new(graph, params);
+            let last_change = engine.run(args.steps)?;
+            let elapsed = start.elapsed();
+            println!("Elapsed => {:?} Last Change => {last_change}", elapsed);
+            Box::new(engine)
+        }
+    };
+    // The contracted nodes only stand in for the original ones during the layout.
+    let engine: Box<dyn LayoutEngine> = match contraction {
+        Some((original, contraction)) => {
+            contraction.expand(&original);
+            Box::new(SimulationState::from_graph(original, params))
+        }
+        None => engine,
+    };
+
+    if args.remove_overlaps {
+        let removal = OverlapRemoval {
+            label_size: args.label_size,
+            ..OverlapRemoval::default()
+        };
+        let before = removal.count(engine.nodes());
+        let after = removal.run(engine.nodes());
+        println!("Overlaps => {before} Remaining => {after}");
+    }
 
-    let rendered = state.render(args.width, args.height);
+    let path = match &args.highlight_path {
+        Some(ends) => Some(shortest_path(engine.as_ref(), ends[0], ends[1])?),
+        None => None,
+    };
 
-    let mut out_file = std::fs::File::create(args.out)?;
+    let rendered = match args.format {
+        Format::Svg => {
+            let mut renderer = engine.renderer();
+            if let Some(path) = path {
+                renderer.highlight_path(path);
+            }
+            if let Some(priority) = args.labels {
+                let placement = LabelPlacement {
+                    font_size: args.font_size,
+                    leader_lines: args.leader_lines,
+                    ..LabelPlacement::default()
+                };
+                renderer.add_labels(
+                    labels::labels(engine.nodes(), engine.relations(), priority.into()),
+                    placement,
+                );
+            }
+            renderer.render(args.width, args.height)
+        }
+        Format::Html | Format::Json | Format::Csv => {
+            let mut layout = engine.layout();
+            for (centrality, values) in &centralities {
+                analysis::annotate(&mut layout, centrality.name(), &nodes, values);
+            }
+            if let Some(path) = path {
+                layout.path = path.iter().map(|e| engine.nodes()[*e].id()).collect();
+            }
+            match args.format {
+                Format::Html => html::render(&layout, args.width, args.height)?,
+                Format::Json => layout.to_json()?,
+                _ => layout.to_csv()?,
+            }
+        }
+    };
+
+    let mut out_file = std::fs::File::create(&args.out)?;
     out_file.write_all(rendered.as_bytes())?;
 
     Ok(())
 }
+
+/// Energy after the simulation, only computed when reports were asked for since it costs as
+/// much as a step.
+fn final_energy(state: &SimulationState, args: &RunArgs) -> String {
+    if args.report_every.is_some() || args.trace.is_some() {
+        format!(" Energy => {}", state.energy())
+    } else {
+        String::new()
+    }
+}
+
+fn simulate(state: &SimulationState, args: &RunArgs) -> Result<f32, Box<dyn Error>> {
+    if args.report_every.is_none() && args.trace.is_none() {
+        return Ok(state.run_n_steps(args.steps)?);
+    }
+
+    let interval = args.report_every.unwrap_or(TRACE_INTERVAL).max(1);
+    let mut trace = match &args.trace {
+        Some(path) => Some(TraceWriter::new(std::fs::File::create(path)?)),
+        None => None,
+    };
+    let mut change = 0.0;
+    let mut remaining = args.steps;
+    while remaining > 0 {
+        let stats = state.run_batch_with_energy(interval.min(remaining))?;
+        remaining -= interval.min(remaining);
+        change += stats.displacement;
+        if args.report_every.is_some() {
+            println!(
+                "Step => {} Energy => {} Change => {} Max Change => {}",
+                stats.step,
+                stats.energy.unwrap_or_default(),
+                stats.displacement,
+                stats.max_displacement
+            );
+        }
+        if let Some(trace) = trace.as_mut() {
+            trace.write(&stats)?;
+        }
+    }
+    if let Some(trace) = trace.as_mut() {
+        trace.flush()?;
+    }
+    Ok(change)
+}
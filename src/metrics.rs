@@ -0,0 +1,326 @@
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{adjacency::Adjacency, export::Layout, sim::map_ranges};
+
+/// Quality measures of a finished layout.
+///
+/// All measures are independent of the scale of the layout, so layouts computed with different
+/// parameters can be compared directly.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayoutMetrics {
+    /// Number of pairs of edges crossing each other. Edges sharing a node never cross.
+    pub edge_crossings: usize,
+    /// Number of pairs of nodes whose circles overlap.
+    pub node_overlaps: usize,
+    /// Variance of the edge lengths divided by the squared mean edge length, the squared
+    /// coefficient of variation.
+    pub edge_length_variance: f32,
+    /// Mean ratio between the smallest angle at a node and the ideal angle for its degree.
+    /// 1.0 means perfectly evenly spread edges.
+    pub angular_resolution: f32,
+    /// Normalized stress against the hop distances after optimal scaling, between 0.0 and 1.0.
+    /// Relation weights are ignored, unlike in the [stress layout](crate::layout::stress), so
+    /// layouts of every algorithm are measured against the same distances.
+    pub stress: f32,
+    /// Mean Jaccard similarity between graph neighbours and nearest neighbours in the layout.
+    pub neighbourhood_preservation: f32,
+}
+
+impl LayoutMetrics {
+    pub fn compute(layout: &Layout) -> Self {
+        let adjacency = Adjacency::from_layout(layout);
+        Self {
+            edge_crossings: edge_crossings(layout),
+            node_overlaps: node_overlaps(layout),
+            edge_length_variance: edge_length_variance(layout),
+            angular_resolution: angular_resolution(layout, &adjacency),
+            stress: stress(layout, &adjacency),
+            neighbourhood_preservation: neighbourhood_preservation(layout, &adjacency),
+        }
+    }
+
+    pub fn to_table(&self) -> String {
+        let rows = [
+            ("edge crossings", self.edge_crossings.to_string()),
+            ("node overlaps", self.node_overlaps.to_string()),
+            (
+                "edge length variance",
+                format!("{:.4}", self.edge_length_variance),
+            ),
+            (
+                "angular resolution",
+                format!("{:.4}", self.angular_resolution),
+            ),
+            ("stress", format!("{:.4}", self.stress)),
+            (
+                "neighbourhood preservation",
+                format!("{:.4}", self.neighbourhood_preservation),
+            ),
+        ];
+        rows.iter()
+            .map(|(name, value)| format!("{name:<28}{value:>12}\n"))
+            .collect()
+    }
+}
+
+fn positions(layout: &Layout) -> Vec<(f32, f32)> {
+    layout.nodes.iter().map(|e| (e.x, e.y)).collect()
+}
+
+fn segments(layout: &Layout) -> Vec<(usize, usize)> {
+    let index = layout.index();
+    layout
+        .edges
+        .iter()
+        .filter_map(|e| Some((*index.get(&e.from)?, *index.get(&e.to)?)))
+        .filter(|(from, to)| from != to)
+        .collect()
+}
+
+#[inline(always)]
+fn orientation(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// Whether the segments `a`-`b` and `c`-`d` properly intersect.
+pub(crate) fn segments_cross(a: (f32, f32), b: (f32, f32), c: (f32, f32), d: (f32, f32)) -> bool {
+    let (o1, o2) = (orientation(a, b, c), orientation(a, b, d));
+    let (o3, o4) = (orientation(c, d, a), orientation(c, d, b));
+    o1 * o2 < 0.0 && o3 * o4 < 0.0
+}
+
+pub fn edge_crossings(layout: &Layout) -> usize {
+    let positions = positions(layout);
+    let segments = segments(layout);
+    map_ranges(segments.len(), |range| {
+        let mut count = 0;
+        for i in range {
+            let (a, b) = segments[i];
+            for &(c, d) in &segments[i + 1..] {
+                if a == c || a == d || b == c || b == d {
+                    continue;
+                }
+                if segments_cross(positions[a], positions[b], positions[c], positions[d]) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    })
+    .into_iter()
+    .sum()
+}
+
+pub fn node_overlaps(layout: &Layout) -> usize {
+    let nodes = &layout.nodes;
+    map_ranges(nodes.len(), |range| {
+        let mut count = 0;
+        for i in range {
+            for other in &nodes[i + 1..] {
+                let distance = (nodes[i].x - other.x).hypot(nodes[i].y - other.y);
+                if distance < nodes[i].drawn_radius() + other.drawn_radius() {
+                    count += 1;
+                }
+            }
+        }
+        count
+    })
+    .into_iter()
+    .sum()
+}
+
+pub fn edge_length_variance(layout: &Layout) -> f32 {
+    let positions = positions(layout);
+    let lengths: Vec<f32> = segments(layout)
+        .into_iter()
+        .map(|(a, b)| (positions[a].0 - positions[b].0).hypot(positions[a].1 - positions[b].1))
+        .collect();
+    if lengths.is_empty() {
+        return 0.0;
+    }
+    let mean = lengths.iter().sum::<f32>() / lengths.len() as f32;
+    if mean <= 0.0 {
+        return 0.0;
+    }
+    lengths
+        .iter()
+        .map(|e| (e / mean - 1.0).powi(2))
+        .sum::<f32>()
+        / lengths.len() as f32
+}
+
+pub fn angular_resolution(layout: &Layout, adjacency: &Adjacency) -> f32 {
+    let positions = positions(layout);
+    let mut total = 0.0;
+    let mut counted = 0;
+    for (node, &(x, y)) in positions.iter().enumerate() {
+        let mut angles: Vec<f32> = adjacency
+            .neighbours(node)
+            .iter()
+            .filter(|(other, _)| *other != node)
+            .map(|&(other, _)| (positions[other].1 - y).atan2(positions[other].0 - x))
+            .collect();
+        if angles.len() < 2 {
+            continue;
+        }
+        angles.sort_by(f32::total_cmp);
+        let wrap = angles[0] + 2.0 * PI - angles[angles.len() - 1];
+        let smallest = angles.windows(2).map(|e| e[1] - e[0]).fold(wrap, f32::min);
+        total += smallest / (2.0 * PI / angles.len() as f32);
+        counted += 1;
+    }
+    if counted == 0 {
+        1.0
+    } else {
+        total / counted as f32
+    }
+}
+
+/// Stress of the layout against the unweighted hop distances, see
+/// [`LayoutMetrics::stress`].
+pub fn stress(layout: &Layout, adjacency: &Adjacency) -> f32 {
+    let positions = positions(layout);
+    // With weights 1/d², the optimally scaled stress reduces to 1 - B² / (A * C).
+    let sums = map_ranges(positions.len(), |range| {
+        let (mut a, mut b, mut c) = (0.0f64, 0.0f64, 0.0f64);
+        for source in range {
+            let hops = adjacency.hops(source);
+            for (target, hop) in hops.iter().enumerate().skip(source + 1) {
+                let d = match hop {
+                    Some(d) if *d > 0 => *d as f64,
+                    _ => continue,
+                };
+                let distance = (positions[source].0 - positions[target].0)
+                    .hypot(positions[source].1 - positions[target].1)
+                    as f64;
+                let w = d.powi(-2);
+                a += w * distance.powi(2);
+                b += w * distance * d;
+                c += 1.0;
+            }
+        }
+        (a, b, c)
+    });
+    let (a, b, c) = sums.into_iter().fold((0.0, 0.0, 0.0), |acc, e| {
+        (acc.0 + e.0, acc.1 + e.1, acc.2 + e.2)
+    });
+    if a <= 0.0 || c <= 0.0 {
+        return 0.0;
+    }
+    (1.0 - b.powi(2) / (a * c)).max(0.0) as f32
+}
+
+pub fn neighbourhood_preservation(layout: &Layout, adjacency: &Adjacency) -> f32 {
+    let positions = positions(layout);
+    let parts = map_ranges(positions.len(), |range| {
+        let mut total = 0.0f32;
+        let mut counted = 0usize;
+        let mut distances: Vec<(f32, usize)> = Vec::with_capacity(positions.len());
+        for node in range {
+            let mut neighbours: Vec<usize> = adjacency
+                .neighbours(node)
+                .iter()
+                .map(|(e, _)| *e)
+                .filter(|e| *e != node)
+                .collect();
+            neighbours.sort_unstable();
+            neighbours.dedup();
+            let k = neighbours.len();
+            if k == 0 {
+                continue;
+            }
+            distances.clear();
+            distances.extend(
+                positions
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != node)
+                    .map(|(other, p)| {
+                        let distance = (p.0 - positions[node].0).hypot(p.1 - positions[node].1);
+                        (distance, other)
+                    }),
+            );
+            if k < distances.len() {
+                distances.select_nth_unstable_by(k - 1, |a, b| a.0.total_cmp(&b.0));
+            }
+            let shared = distances[..k]
+                .iter()
+                .filter(|(_, other)| neighbours.binary_search(other).is_ok())
+                .count();
+            total += shared as f32 / (2 * k - shared) as f32;
+            counted += 1;
+        }
+        (total, counted)
+    });
+    let (total, counted) = parts
+        .into_iter()
+        .fold((0.0, 0), |acc, e| (acc.0 + e.0, acc.1 + e.1));
+    if counted == 0 {
+        1.0
+    } else {
+        total / counted as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{Layout, LayoutEdge, LayoutNode};
+
+    use super::LayoutMetrics;
+
+    fn layout(positions: &[(f32, f32)], edges: &[(usize, usize)]) -> Layout {
+        Layout {
+            nodes: positions
+                .iter()
+                .enumerate()
+                .map(|(id, &(x, y))| LayoutNode {
+                    id,
+                    label: None,
+                    x,
+                    y,
+                    weight: 0.1,
+                    radius: None,
+                    attributes: Default::default(),
+                })
+                .collect(),
+            edges: edges
+                .iter()
+                .map(|&(from, to)| LayoutEdge {
+                    from,
+                    to,
+                    weight: 1.0,
+                    bends: Vec::new(),
+                })
+                .collect(),
+            path: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn square_with_diagonals() {
+        let square = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let cycle = layout(&square, &[(0, 1), (1, 2), (2, 3), (3, 0)]);
+        let metrics = LayoutMetrics::compute(&cycle);
+        assert_eq!(metrics.edge_crossings, 0);
+        assert_eq!(metrics.node_overlaps, 0);
+        assert!(metrics.edge_length_variance.abs() < 1.0E-6);
+        assert!((metrics.angular_resolution - 0.5).abs() < 1.0E-6);
+        assert!((metrics.neighbourhood_preservation - 1.0).abs() < 1.0E-6);
+
+        let crossed = layout(&square, &[(0, 1), (1, 2), (2, 3), (3, 0), (0, 2), (1, 3)]);
+        let metrics = LayoutMetrics::compute(&crossed);
+        assert_eq!(metrics.edge_crossings, 1);
+    }
+
+    #[test]
+    fn stress_of_path() {
+        let straight = layout(&[(0.0, 0.0), (2.0, 0.0), (4.0, 0.0)], &[(0, 1), (1, 2)]);
+        let folded = layout(&[(0.0, 0.0), (2.0, 0.0), (0.1, 0.0)], &[(0, 1), (1, 2)]);
+        let straight = LayoutMetrics::compute(&straight).stress;
+        let folded = LayoutMetrics::compute(&folded).stress;
+        assert!(straight < 1.0E-6, "Stress is {straight}");
+        assert!(folded > straight);
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     iter::Sum,
     ops::{Add, Div, Mul, Neg},
     sync::{Arc, Weak},
@@ -6,6 +7,8 @@ use std::{
 
 use crossbeam::sync::ShardedLock;
 
+use crate::sim::LayoutParams;
+
 #[derive(Copy, Clone, Debug)]
 pub struct Vector2D {
     x: f32,
@@ -113,11 +116,24 @@ impl Add<Vector2D> for Coordinates {
     }
 }
 
+/// Position a node is pulled back to, with a force growing linearly with the distance.
+#[derive(Clone, Copy, Debug)]
+pub struct Anchor {
+    pub at: Coordinates,
+    pub strength: f32,
+}
+
 #[derive(Debug)]
 pub struct Node {
     id: usize,
     pub loc: ShardedLock<Coordinates>,
+    anchor: ShardedLock<Option<Anchor>>,
+    community: ShardedLock<Option<usize>>,
+    radius: ShardedLock<Option<f32>>,
+    pinned: ShardedLock<bool>,
     pub weight: f32,
+    pub label: Option<String>,
+    pub attributes: BTreeMap<String, String>,
     from: ShardedLock<Vec<Weak<Relation>>>,
     to: ShardedLock<Vec<Weak<Relation>>>,
 }
@@ -127,21 +143,95 @@ impl Node {
         Self {
             id,
             loc: ShardedLock::new(Coordinates { x, y }),
+            anchor: ShardedLock::new(None),
+            community: ShardedLock::new(None),
+            radius: ShardedLock::new(None),
+            pinned: ShardedLock::new(false),
             weight,
+            label: None,
+            attributes: BTreeMap::new(),
             from: ShardedLock::new(Vec::new()),
             to: ShardedLock::new(Vec::new()),
         }
     }
 
+    pub fn with_label(mut self, label: Option<String>) -> Self {
+        self.label = label;
+        self
+    }
+
+    pub fn with_attributes(mut self, attributes: BTreeMap<String, String>) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn anchor(&self) -> Option<Anchor> {
+        *self.anchor.read().unwrap()
+    }
+
+    pub fn set_anchor(&self, anchor: Option<Anchor>) {
+        *self.anchor.write().unwrap() = anchor;
+    }
+
+    pub fn community(&self) -> Option<usize> {
+        *self.community.read().unwrap()
+    }
+
+    pub fn set_community(&self, community: Option<usize>) {
+        *self.community.write().unwrap() = community;
+    }
+
+    /// Radius the node is drawn with, the absolute weight unless set otherwise.
+    pub fn radius(&self) -> f32 {
+        self.radius.read().unwrap().unwrap_or(self.weight.abs())
+    }
+
+    /// Radius set by [`Self::set_radius`], `None` while it follows the weight.
+    pub fn radius_override(&self) -> Option<f32> {
+        *self.radius.read().unwrap()
+    }
+
+    pub fn set_radius(&self, radius: Option<f32>) {
+        *self.radius.write().unwrap() = radius;
+    }
+
+    /// Relations registered with this node, starting or ending at it.
+    pub(crate) fn relations(&self) -> Vec<Arc<Relation>> {
+        let from = self.from.read().unwrap();
+        let to = self.to.read().unwrap();
+        from.iter()
+            .chain(to.iter())
+            .filter_map(Weak::upgrade)
+            .collect()
+    }
+
+    /// Pinned nodes keep their position, the simulation only moves the other nodes around them.
+    pub fn pinned(&self) -> bool {
+        *self.pinned.read().unwrap()
+    }
+
+    pub fn set_pinned(&self, pinned: bool) {
+        *self.pinned.write().unwrap() = pinned;
+    }
+
+    /// Position after one step, with `centre` the [centre of mass](Self::centre) of `other`
+    /// pulling it through gravity.
     pub fn calc_new_position(
         &self,
         other: &[Arc<Self>],
-        spring_scale: f32,
-        coloumb_scale: f32,
-        t: f32,
+        centre: Coordinates,
+        params: &LayoutParams,
     ) -> Coordinates {
-        let offset = self.compound_vector(other, spring_scale, coloumb_scale);
-        *self.loc.read().unwrap() + offset.travel(t)
+        if self.pinned() {
+            return *self.loc.read().unwrap();
+        }
+        let offset = self.compound_vector(other, centre, params);
+        *self.loc.read().unwrap() + offset.travel(params.time_delta)
     }
 
     pub fn update_coordinates(&self, new: Coordinates) {
@@ -181,46 +271,220 @@ impl Node {
         direction * force
     }
 
+    /// Depth by which the circles of both nodes overlap, 0.0 if they don't.
+    #[inline(always)]
+    fn overlap(&self, other: &Self) -> f32 {
+        let reach = self.radius() + other.radius();
+        (reach - self.distance_squared(other).sqrt()).max(0.0)
+    }
+
+    /// Push away from an overlapping node, growing linearly with the depth of the overlap.
+    #[inline(always)]
+    fn collision_vector(&self, other: &Self, scale: f32) -> Vector2D {
+        if scale == 0.0 {
+            return Vector2D::ZERO;
+        }
+        let overlap = self.overlap(other);
+        if overlap <= 0.0 {
+            return Vector2D::ZERO;
+        }
+        let delta = other.loc.read().unwrap().to(*self.loc.read().unwrap());
+        if delta.length() <= f32::EPSILON {
+            return Vector2D::ZERO;
+        }
+        delta.normalize() * (scale * overlap)
+    }
+
+    #[inline(always)]
+    fn collision_energy(&self, other: &Self, scale: f32) -> f32 {
+        if scale == 0.0 {
+            return 0.0;
+        }
+        0.5 * scale * self.overlap(other).powi(2)
+    }
+
+    /// Pull towards a node of the same community, growing linearly with the distance.
+    #[inline(always)]
+    fn community_vector(&self, other: &Self, scale: f32) -> Vector2D {
+        if scale == 0.0 {
+            return Vector2D::ZERO;
+        }
+        match (self.community(), other.community()) {
+            (Some(a), Some(b)) if a == b => {
+                self.loc.read().unwrap().to(*other.loc.read().unwrap()) * scale
+            }
+            _ => Vector2D::ZERO,
+        }
+    }
+
+    #[inline(always)]
+    fn community_energy(&self, other: &Self, scale: f32) -> f32 {
+        if scale == 0.0 {
+            return 0.0;
+        }
+        match (self.community(), other.community()) {
+            (Some(a), Some(b)) if a == b => 0.5 * scale * self.distance_squared(other),
+            _ => 0.0,
+        }
+    }
+
     #[inline(always)]
     fn spring_vector(&self, scale: f32) -> Vector2D {
         let from_guard = self.from.read().unwrap();
         let to_guard = self.to.read().unwrap();
         let from_iter = from_guard
             .iter()
-            .map(|e| e.upgrade().unwrap().hook_vector(scale));
+            .filter_map(Weak::upgrade)
+            .map(|e| e.hook_vector(scale));
         let to_iter = to_guard
             .iter()
-            .map(|e| -e.upgrade().unwrap().hook_vector(scale));
+            .filter_map(Weak::upgrade)
+            .map(|e| -e.hook_vector(scale));
 
         from_iter.chain(to_iter).sum()
     }
 
+    /// Centre of mass of `nodes`, weighted by the node weights. Computed once per step, as it
+    /// is the same for every node.
+    pub fn centre(nodes: &[Arc<Self>]) -> Coordinates {
+        if nodes.is_empty() {
+            return Coordinates { x: 0.0, y: 0.0 };
+        }
+        let (x, y, total) = nodes.iter().fold((0.0, 0.0, 0.0), |acc, e| {
+            let loc = *e.loc.read().unwrap();
+            let weight = e.weight.abs();
+            (
+                acc.0 + loc.x * weight,
+                acc.1 + loc.y * weight,
+                acc.2 + weight,
+            )
+        });
+        if total > 0.0 {
+            Coordinates {
+                x: x / total,
+                y: y / total,
+            }
+        } else {
+            *nodes[0].loc.read().unwrap()
+        }
+    }
+
+    /// Pull towards the centre of mass of all nodes, growing linearly with the distance.
+    #[inline(always)]
+    fn gravity_vector(&self, centre: Coordinates, scale: f32) -> Vector2D {
+        if scale == 0.0 {
+            return Vector2D::ZERO;
+        }
+        self.loc.read().unwrap().to(centre) * (scale * self.weight.abs())
+    }
+
+    #[inline(always)]
+    fn gravity_energy(&self, centre: Coordinates, scale: f32) -> f32 {
+        if scale == 0.0 {
+            return 0.0;
+        }
+        let distance = self.loc.read().unwrap().to(centre).length();
+        0.5 * scale * self.weight.abs() * distance.powi(2)
+    }
+
+    #[inline(always)]
+    fn anchor_vector(&self) -> Vector2D {
+        match self.anchor() {
+            Some(anchor) => self.loc.read().unwrap().to(anchor.at) * anchor.strength,
+            None => Vector2D::ZERO,
+        }
+    }
+
+    #[inline(always)]
+    fn anchor_energy(&self) -> f32 {
+        match self.anchor() {
+            Some(anchor) => {
+                0.5 * anchor.strength * self.loc.read().unwrap().to(anchor.at).length().powi(2)
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Potential energy of this node: its share of the coloumb, collision and community energy
+    /// towards every other node plus the spring energy of the relations starting at it and the
+    /// energy of its gravity and anchor.
+    ///
+    /// Summed over all nodes this yields the energy of the whole system.
+    pub fn potential_energy(
+        &self,
+        other: &[Arc<Self>],
+        centre: Coordinates,
+        params: &LayoutParams,
+    ) -> f32 {
+        let coloumb: f32 = other
+            .iter()
+            .filter(|e| e.id != self.id)
+            .map(|e| {
+                self.coloumb_energy(e, params.coloumb_scale)
+                    + self.collision_energy(e, params.collision_scale)
+                    + self.community_energy(e, params.community_scale)
+            })
+            .sum();
+        let spring: f32 = self
+            .from
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|e| e.hook_energy(params.spring_scale))
+            .sum();
+        coloumb * 0.5
+            + spring
+            + self.gravity_energy(centre, params.gravity_scale)
+            + self.anchor_energy()
+    }
+
+    #[inline(always)]
+    fn coloumb_energy(&self, other: &Self, scale: f32) -> f32 {
+        self.coloumb_force(other, scale) * self.distance_squared(other).sqrt()
+    }
+
     #[inline(always)]
     fn compound_vector(
         &self,
         other: &[Arc<Self>],
-        spring_scale: f32,
-        coloumb_scale: f32,
+        centre: Coordinates,
+        params: &LayoutParams,
     ) -> Vector2D {
         let tmp: Vector2D = other
             .iter()
             .filter(|e| e.id != self.id)
-            .map(|e| self.coloumb_vector(e, coloumb_scale))
+            .map(|e| {
+                self.coloumb_vector(e, params.coloumb_scale)
+                    + self.collision_vector(e, params.collision_scale)
+                    + self.community_vector(e, params.community_scale)
+            })
             .sum();
-        tmp + self.spring_vector(spring_scale)
+        tmp + self.spring_vector(params.spring_scale)
+            + self.gravity_vector(centre, params.gravity_scale)
+            + self.anchor_vector()
     }
 }
 
 #[derive(Debug)]
 pub struct Relation {
+    pub weight: f32,
     pub weight_squared: f32,
     pub from: Arc<Node>,
     pub to: Arc<Node>,
 }
 
 impl Relation {
-    pub fn new(weight: f32, from: Arc<Node>, to: Arc<Node>) -> Self {
+    /// Creates a relation between two nodes and registers it with both of them.
+    pub fn connect(weight: f32, from: Arc<Node>, to: Arc<Node>) -> Arc<Self> {
+        let relation = Arc::new(Self::new(weight, from, to));
+        relation.register();
+        relation
+    }
+
+    pub(crate) fn new(weight: f32, from: Arc<Node>, to: Arc<Node>) -> Self {
         Self {
+            weight,
             weight_squared: weight.powi(2),
             from,
             to,
@@ -243,24 +507,62 @@ impl Relation {
         self.hook_force_squared(scale).sqrt()
     }
 
+    #[inline(always)]
+    fn hook_energy(&self, scale: f32) -> f32 {
+        0.5 * self.hook_force(scale) * self.distance_squared().sqrt()
+    }
+
+    /// Pull of the spring on `from`, towards `to`.
+    ///
+    /// Scales the distance vector by force / length at once instead of normalizing it first,
+    /// which rounds twice and misses exact results like an axis-aligned pull of 2.0.
     #[inline(always)]
     fn hook_vector(&self, scale: f32) -> Vector2D {
         let force = self.hook_force(scale);
-        let direction = self
+        let delta = self
             .from
             .loc
             .read()
             .unwrap()
-            .to(*self.to.loc.read().unwrap())
-            .normalize();
-        direction * force
+            .to(*self.to.loc.read().unwrap());
+        delta * (force / delta.length())
     }
 
-    pub fn register(self: &Arc<Self>) {
+    pub(crate) fn register(self: &Arc<Self>) {
         let weak_from = Arc::downgrade(self);
         let weak_to = Arc::downgrade(self);
-        self.from.from.write().unwrap().push(weak_from);
-        self.to.to.write().unwrap().push(weak_to);
+        // Relations removed from a graph leave dangling references behind, dropped here.
+        let mut from = self.from.from.write().unwrap();
+        from.retain(|e| e.strong_count() > 0);
+        from.push(weak_from);
+        drop(from);
+        let mut to = self.to.to.write().unwrap();
+        to.retain(|e| e.strong_count() > 0);
+        to.push(weak_to);
+    }
+
+    /// Removes this relation from its nodes again, so they stop feeling its spring while it is
+    /// still referenced elsewhere.
+    pub(crate) fn unregister(&self) {
+        let this: *const Self = self;
+        let keep = |e: &Weak<Self>| e.strong_count() > 0 && e.as_ptr() != this;
+        self.from.from.write().unwrap().retain(keep);
+        self.to.to.write().unwrap().retain(keep);
+    }
+}
+
+/// A set of nodes together with the relations between them.
+///
+/// Use [`GraphBuilder`](crate::builder::GraphBuilder) to assemble one from plain ids.
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    pub nodes: Vec<Arc<Node>>,
+    pub relations: Vec<Arc<Relation>>,
+}
+
+impl Graph {
+    pub fn new(nodes: Vec<Arc<Node>>, relations: Vec<Arc<Relation>>) -> Self {
+        Self { nodes, relations }
     }
 }
 
@@ -268,6 +570,8 @@ impl Relation {
 mod tests {
     use std::sync::Arc;
 
+    use crate::sim::LayoutParams;
+
     use super::{Node, Relation};
 
     #[test]
@@ -302,9 +606,14 @@ mod tests {
         let from = Arc::new(Node::new(1, 0.0, 0.0, 1.0));
         let to = Arc::new(Node::new(2, 2.0, 2.0, 1.0));
         let nodes = Vec::from([from.clone(), to.clone()]);
-        let r = Arc::new(Relation::new(1.0, from, to));
-        r.register();
-        let new_coordinates = nodes[0].calc_new_position(&nodes, 1.0, 1.0, 1.0);
+        let _relation = Relation::connect(1.0, from, to);
+        let params = LayoutParams {
+            spring_scale: 1.0,
+            coloumb_scale: 1.0,
+            time_delta: 1.0,
+            ..LayoutParams::default()
+        };
+        let new_coordinates = nodes[0].calc_new_position(&nodes, Node::centre(&nodes), &params);
         dbg!(new_coordinates);
     }
 }
@@ -0,0 +1,268 @@
+//! Removal of overlapping nodes from a finished layout.
+
+use std::sync::Arc;
+
+use crate::{
+    layout::{
+        read_positions,
+        stress::{majorize, Term},
+        write_positions,
+    },
+    model::Node,
+};
+
+/// Width of a glyph of a monospace font relative to the font size.
+pub const CHARACTER_WIDTH: f32 = 0.6;
+
+/// Largest factor a distance may grow by in a single round, keeps the layout from exploding.
+const MAX_EXPANSION: f32 = 1.5;
+/// Majorization steps spent on the proximity graph of one round.
+const MAJORIZATION_STEPS: usize = 30;
+/// Weight of pairs keeping their distance relative to overlapping pairs. Smaller than one, so
+/// a crowd of neighbours cannot hold two overlapping nodes together.
+const KEEP_WEIGHT: f32 = 0.1;
+
+/// Width and height of `label` set in the font size `size`.
+pub fn label_extent(label: &str, size: f32) -> (f32, f32) {
+    (label.chars().count() as f32 * size * CHARACTER_WIDTH, size)
+}
+
+/// Area a node claims, centred on the node.
+#[derive(Copy, Clone, Debug)]
+enum Shape {
+    Circle {
+        radius: f32,
+    },
+    /// A label set below the circle. Only the extent matters, as every box is offset from its
+    /// node the same way.
+    Box {
+        half_width: f32,
+        half_height: f32,
+    },
+}
+
+impl Shape {
+    fn half_extent(self) -> (f32, f32) {
+        match self {
+            Shape::Circle { radius } => (radius, radius),
+            Shape::Box {
+                half_width,
+                half_height,
+            } => (half_width, half_height),
+        }
+    }
+}
+
+/// Factor the distance between two shapes has to grow by so they no longer overlap. Values of
+/// 1.0 or less mean they are already apart.
+fn expansion(a: Shape, b: Shape, delta: (f32, f32), margin: f32) -> f32 {
+    match (a, b) {
+        (Shape::Circle { radius: ra }, Shape::Circle { radius: rb }) => {
+            (ra + rb + margin) / delta.0.hypot(delta.1)
+        }
+        _ => {
+            let (aw, ah) = a.half_extent();
+            let (bw, bh) = b.half_extent();
+            let width = (aw + bw + margin) / delta.0.abs();
+            let height = (ah + bh + margin) / delta.1.abs();
+            width.min(height)
+        }
+    }
+}
+
+/// Overlap removal in the spirit of PRISM by Gansner and Hu.
+///
+/// Every round builds a proximity graph of nearby nodes. Overlapping pairs get a target
+/// distance that would separate them, all other pairs keep their current distance with less
+/// weight, and the positions are fitted to these targets with stress majorization. Moving along
+/// the lines between the nodes keeps their relative placement intact.
+#[derive(Copy, Clone, Debug)]
+pub struct OverlapRemoval {
+    /// Space kept free between two shapes.
+    pub margin: f32,
+    /// Font size of the labels in layout units. Labelled nodes then claim the box of their
+    /// circle with the label below it, `None` only separates the circles.
+    pub label_size: Option<f32>,
+    /// Upper bound of rounds.
+    pub rounds: usize,
+}
+
+impl Default for OverlapRemoval {
+    fn default() -> Self {
+        Self {
+            margin: 0.0,
+            label_size: None,
+            rounds: 100,
+        }
+    }
+}
+
+impl OverlapRemoval {
+    fn shapes(&self, nodes: &[Arc<Node>]) -> Vec<Shape> {
+        nodes
+            .iter()
+            .map(|node| {
+                let radius = node.radius();
+                match self.label_size {
+                    None => Shape::Circle { radius },
+                    Some(size) => {
+                        let (width, height) = node
+                            .label
+                            .as_deref()
+                            .map_or((0.0, 0.0), |e| label_extent(e, size));
+                        Shape::Box {
+                            half_width: radius.max(width / 2.0),
+                            half_height: radius + height / 2.0,
+                        }
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Pairs of nodes close enough to take part in a round, found by sweeping along x.
+    fn proximity(&self, positions: &[(f32, f32)], shapes: &[Shape]) -> Vec<(usize, usize)> {
+        let mut order: Vec<usize> = (0..positions.len()).collect();
+        order.sort_by(|a, b| positions[*a].0.total_cmp(&positions[*b].0));
+        let widest = shapes.iter().map(|e| e.half_extent().0).fold(0.0, f32::max);
+        let mut pairs = Vec::new();
+        for (k, &i) in order.iter().enumerate() {
+            let (iw, ih) = shapes[i].half_extent();
+            let reach = 2.0 * (iw + widest + self.margin);
+            for &j in &order[k + 1..] {
+                let dx = positions[j].0 - positions[i].0;
+                if dx > reach {
+                    break;
+                }
+                let (jw, jh) = shapes[j].half_extent();
+                let dy = (positions[j].1 - positions[i].1).abs();
+                if dx < 2.0 * (iw + jw + self.margin) && dy < 2.0 * (ih + jh + self.margin) {
+                    pairs.push((i.min(j), i.max(j)));
+                }
+            }
+        }
+        pairs
+    }
+
+    fn overlaps(&self, positions: &[(f32, f32)], shapes: &[Shape]) -> usize {
+        self.proximity(positions, shapes)
+            .into_iter()
+            .filter(|&(i, j)| {
+                let delta = (
+                    positions[j].0 - positions[i].0,
+                    positions[j].1 - positions[i].1,
+                );
+                expansion(shapes[i], shapes[j], delta, self.margin) > 1.0
+            })
+            .count()
+    }
+
+    /// Number of overlapping pairs of nodes.
+    pub fn count(&self, nodes: &[Arc<Node>]) -> usize {
+        self.overlaps(&read_positions(nodes), &self.shapes(nodes))
+    }
+
+    /// Moves the nodes apart and returns the number of pairs still overlapping.
+    pub fn run(&self, nodes: &[Arc<Node>]) -> usize {
+        let shapes = self.shapes(nodes);
+        let mut positions = read_positions(nodes);
+        for _ in 0..self.rounds {
+            let mut terms: Vec<Vec<Term>> = vec![Vec::new(); positions.len()];
+            let mut overlapping = 0;
+            for (i, j) in self.proximity(&positions, &shapes) {
+                let mut delta = (
+                    positions[j].0 - positions[i].0,
+                    positions[j].1 - positions[i].1,
+                );
+                if delta.0.hypot(delta.1) <= f32::EPSILON {
+                    // Nodes on the same spot have no direction to move in, pick one by index.
+                    let angle = j as f32 * 2.399_963;
+                    let (aw, ah) = shapes[i].half_extent();
+                    let nudge = 1.0E-3 * aw.max(ah).max(1.0);
+                    delta = (angle.cos() * nudge, angle.sin() * nudge);
+                    positions[j] = (positions[i].0 + delta.0, positions[i].1 + delta.1);
+                }
+                let distance = delta.0.hypot(delta.1);
+                let factor = expansion(shapes[i], shapes[j], delta, self.margin);
+                let target = if factor > 1.0 {
+                    overlapping += 1;
+                    // Nodes almost on the same spot would need many rounds to grow apart by a
+                    // factor, so they get at least half of the required distance at once.
+                    let required = distance * factor;
+                    required.min((distance * MAX_EXPANSION).max(required / 2.0))
+                } else {
+                    distance
+                };
+                let weight = if factor > 1.0 { 1.0 } else { KEEP_WEIGHT } * target.powi(-2);
+                terms[i].push(Term {
+                    other: j,
+                    distance: target,
+                    weight,
+                });
+                terms[j].push(Term {
+                    other: i,
+                    distance: target,
+                    weight,
+                });
+            }
+            if overlapping == 0 {
+                break;
+            }
+            majorize(&mut positions, &terms, MAJORIZATION_STEPS, 1.0E-4);
+        }
+        write_positions(nodes, &positions);
+        self.overlaps(&positions, &shapes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{builder::GraphBuilder, layout::read_positions};
+
+    use super::OverlapRemoval;
+
+    #[test]
+    fn separates_circles_and_keeps_order() {
+        let mut builder = GraphBuilder::new();
+        for i in 0..5 {
+            builder.add_node_at(i, i as f32 * 0.5, 0.0, 1.0).unwrap();
+        }
+        builder.add_node_at(5, 0.0, 0.0, 2.0).unwrap();
+        let graph = builder.build();
+        let removal = OverlapRemoval::default();
+        assert!(removal.count(&graph.nodes) > 0);
+        assert_eq!(removal.run(&graph.nodes), 0);
+
+        let positions = read_positions(&graph.nodes);
+        for i in 0..4 {
+            assert!(
+                positions[i].0 < positions[i + 1].0,
+                "Node {i} swapped places"
+            );
+        }
+    }
+
+    #[test]
+    fn separates_labels() {
+        let mut builder = GraphBuilder::new();
+        builder
+            .add_node_at(1, 0.0, 0.0, 1.0)
+            .unwrap()
+            .label(1, "Berlin Hauptbahnhof")
+            .unwrap()
+            .add_node_at(2, 4.0, 0.0, 1.0)
+            .unwrap()
+            .label(2, "Berlin Ostbahnhof")
+            .unwrap();
+        let graph = builder.build();
+        let circles = OverlapRemoval::default();
+        assert_eq!(circles.count(&graph.nodes), 0);
+
+        let labels = OverlapRemoval {
+            label_size: Some(1.0),
+            ..OverlapRemoval::default()
+        };
+        assert_eq!(labels.count(&graph.nodes), 1);
+        assert_eq!(labels.run(&graph.nodes), 0);
+    }
+}
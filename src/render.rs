@@ -1,8 +1,20 @@
-use crate::model::{Node, Relation};
+use crate::{
+    labels::{Label, LabelPlacement},
+    model::{Node, Relation},
+};
 
 pub struct Renderer {
-    elements: Vec<Element>,
+    elements: Vec<(Element, Option<Part>)>,
     bounds: Bounds,
+    labels: Vec<Label>,
+    placement: Option<LabelPlacement>,
+    path: Option<Vec<usize>>,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Renderer {
@@ -15,13 +27,123 @@ impl Renderer {
                 min_y: 0.0,
                 max_y: 0.0,
             },
+            labels: Vec::new(),
+            placement: None,
+            path: None,
         }
     }
 
     pub fn add_element(&mut self, element: Element) {
+        self.push(element, None);
+    }
+
+    /// Adds an element drawing the given part of the graph.
+    pub fn add_part(&mut self, part: Part, element: Element) {
+        self.push(element, Some(part));
+    }
+
+    fn push(&mut self, element: Element, part: Option<Part>) {
         let bounds = element.bounds();
         self.bounds.update(bounds);
-        self.elements.push(element);
+        self.elements.push((element, part));
+    }
+
+    /// Sets the labels, which are placed once the scale of the image is known.
+    pub fn add_labels(&mut self, labels: Vec<Label>, placement: LabelPlacement) {
+        self.labels = labels;
+        self.placement = Some(placement);
+    }
+
+    /// Highlights the nodes of `path`, given by their index, and the edges between consecutive
+    /// ones, and dims all other parts of the graph. Elements added without a part, like the
+    /// labels, stay as they are.
+    pub fn highlight_path(&mut self, path: Vec<usize>) {
+        self.path = Some(path);
+    }
+
+    fn emphasis(&self, part: Option<Part>) -> Emphasis {
+        let (Some(path), Some(part)) = (&self.path, part) else {
+            return Emphasis::Normal;
+        };
+        let on_path = match part {
+            Part::Node(node) => path.contains(&node),
+            Part::Edge(from, to) => path
+                .windows(2)
+                .any(|e| (e[0], e[1]) == (from, to) || (e[0], e[1]) == (to, from)),
+        };
+        if on_path {
+            Emphasis::Highlighted
+        } else {
+            Emphasis::Dimmed
+        }
+    }
+
+    /// Places the labels in pixels and returns them as elements in layout coordinates.
+    fn place_labels(&self, x: f32, y: f32, x_scale: f32, y_scale: f32) -> Vec<Element> {
+        let Some(placement) = self.placement else {
+            return Vec::new();
+        };
+        let to_pixels = |(px, py): (f32, f32)| {
+            (
+                (px - self.bounds.min_x) * x_scale,
+                (py - self.bounds.min_y) * y_scale,
+            )
+        };
+        let to_layout = |(px, py): (f32, f32)| {
+            (
+                px / x_scale + self.bounds.min_x,
+                py / y_scale + self.bounds.min_y,
+            )
+        };
+        let mut circles = Vec::new();
+        let mut segments = Vec::new();
+        for (element, _) in &self.elements {
+            match element {
+                Element::Circle { radius, x, y, .. } => {
+                    circles.push((to_pixels((*x, *y)), *radius))
+                }
+                Element::Line { start, stop } => {
+                    segments.push((to_pixels(*start), to_pixels(*stop)))
+                }
+                Element::Polyline { points } => segments.extend(
+                    points
+                        .windows(2)
+                        .map(|e| (to_pixels(e[0]), to_pixels(e[1]))),
+                ),
+                Element::Tag { .. } | Element::Leader { .. } => {}
+            }
+        }
+        // Circles are drawn with their radius in pixels, so labels keep theirs too.
+        let labels: Vec<Label> = self
+            .labels
+            .iter()
+            .map(|e| {
+                let (px, py) = to_pixels((e.x, e.y));
+                Label {
+                    x: px,
+                    y: py,
+                    ..e.clone()
+                }
+            })
+            .collect();
+
+        let mut elements = Vec::new();
+        for label in placement.place(&labels, &circles, &segments, x, y) {
+            if let Some((start, stop)) = label.leader {
+                elements.push(Element::Leader {
+                    start: to_layout(start),
+                    stop: to_layout(stop),
+                });
+            }
+            let (tx, ty) = to_layout((label.x, label.y));
+            elements.push(Element::Tag {
+                content: label.content,
+                x: tx,
+                y: ty,
+                size: placement.font_size,
+            });
+        }
+        elements
     }
 
     pub fn render(self, x: f32, y: f32) -> String {
@@ -34,10 +156,26 @@ impl Renderer {
         let x_scale = x / outer_x;
         let y_scale = y / outer_y;
 
-        let inner_svg_parts: Vec<String> = self
+        let labels = self.place_labels(x, y, x_scale, y_scale);
+        let mut styled: Vec<(&Element, Emphasis)> = self
             .elements
             .iter()
-            .map(|e| e.render(x_scale, self.bounds.min_x, y_scale, self.bounds.min_y))
+            .map(|(e, part)| (e, self.emphasis(*part)))
+            .chain(labels.iter().map(|e| (e, Emphasis::Normal)))
+            .collect();
+        // The highlighted path is drawn on top of the rest.
+        styled.sort_by_key(|(_, emphasis)| *emphasis == Emphasis::Highlighted);
+        let inner_svg_parts: Vec<String> = styled
+            .into_iter()
+            .map(|(e, emphasis)| {
+                e.render_with(
+                    x_scale,
+                    self.bounds.min_x,
+                    y_scale,
+                    self.bounds.min_y,
+                    emphasis,
+                )
+            })
             .collect();
 
         let inner_svg = inner_svg_parts.join("\n");
@@ -52,11 +190,64 @@ impl Renderer {
     }
 }
 
+/// Part of the graph an element draws, by the indices of its nodes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Part {
+    Node(usize),
+    Edge(usize, usize),
+}
+
+/// How an element stands out from the rest.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Emphasis {
+    Normal,
+    Highlighted,
+    Dimmed,
+}
+
+impl Emphasis {
+    fn stroke(self) -> &'static str {
+        match self {
+            Emphasis::Normal => r#"stroke="black" stroke-width="2px""#,
+            Emphasis::Highlighted => r##"stroke="#d62728" stroke-width="4px""##,
+            Emphasis::Dimmed => r#"stroke="black" stroke-width="2px" opacity="0.2""#,
+        }
+    }
+}
+
+/// Fill colours of the communities, repeated for more communities than colours.
+const PALETTE: [&str; 10] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f", "#bab0ac",
+];
+
 pub enum Element {
-    Circle { radius: f32, x: f32, y: f32 },
-    Line { start: (f32, f32), stop: (f32, f32) },
-    #[allow(dead_code)]
-    Tag { content: String, x: f32, y: f32 },
+    /// Circles without a `fill` are drawn black.
+    Circle {
+        radius: f32,
+        x: f32,
+        y: f32,
+        fill: Option<&'static str>,
+    },
+    Line {
+        start: (f32, f32),
+        stop: (f32, f32),
+    },
+    Polyline {
+        points: Vec<(f32, f32)>,
+    },
+    /// Text centred on the given point, `size` is the font size in pixels.
+    Tag {
+        content: String,
+        x: f32,
+        y: f32,
+        size: f32,
+    },
+    /// Thin line from a node to its label.
+    Leader {
+        start: (f32, f32),
+        stop: (f32, f32),
+    },
 }
 
 struct Bounds {
@@ -78,18 +269,32 @@ impl Bounds {
 impl Element {
     fn bounds(&self) -> Bounds {
         match self {
-            Element::Circle { radius, x, y } => Bounds {
+            Element::Circle { radius, x, y, .. } => Bounds {
                 min_x: x - radius,
                 max_x: x + radius,
                 min_y: y - radius,
                 max_y: y + radius,
             },
-            Element::Line { start, stop } => Bounds {
+            Element::Line { start, stop } | Element::Leader { start, stop } => Bounds {
                 min_x: start.0.min(stop.0),
                 max_x: start.0.max(stop.0),
                 min_y: start.1.min(stop.1),
                 max_y: start.1.max(stop.1),
             },
+            Element::Polyline { points } => points.iter().fold(
+                Bounds {
+                    min_x: f32::INFINITY,
+                    max_x: f32::NEG_INFINITY,
+                    min_y: f32::INFINITY,
+                    max_y: f32::NEG_INFINITY,
+                },
+                |acc, (x, y)| Bounds {
+                    min_x: acc.min_x.min(*x),
+                    max_x: acc.max_x.max(*x),
+                    min_y: acc.min_y.min(*y),
+                    max_y: acc.max_y.max(*y),
+                },
+            ),
             Element::Tag { x, y, .. } => Bounds {
                 min_x: *x,
                 max_x: *x,
@@ -100,10 +305,23 @@ impl Element {
     }
 
     pub fn render(&self, x_scale: f32, x_offset: f32, y_scale: f32, y_offset: f32) -> String {
+        self.render_with(x_scale, x_offset, y_scale, y_offset, Emphasis::Normal)
+    }
+
+    pub fn render_with(
+        &self,
+        x_scale: f32,
+        x_offset: f32,
+        y_scale: f32,
+        y_offset: f32,
+        emphasis: Emphasis,
+    ) -> String {
+        let stroke = emphasis.stroke();
         match self {
-            Element::Circle { radius, x, y } => {
+            Element::Circle { radius, x, y, fill } => {
                 let (new_x, new_y) = ((x - x_offset) * x_scale, (y - y_offset) * y_scale);
-                format!(r#"<circle stroke="black" stroke-width="2px" cx="{new_x}" cy="{new_y}" r="{radius}" />"#)
+                let fill = fill.map(|e| format!(r#" fill="{e}""#)).unwrap_or_default();
+                format!(r#"<circle {stroke} cx="{new_x}" cy="{new_y}" r="{radius}"{fill} />"#)
             }
             Element::Line { start, stop } => {
                 let (x1, y1) = (
@@ -111,21 +329,61 @@ impl Element {
                     (start.1 - y_offset) * y_scale,
                 );
                 let (x2, y2) = ((stop.0 - x_offset) * x_scale, (stop.1 - y_offset) * y_scale);
-                format!(r#"<line stroke="black" stroke-width="2px" x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}" />"#)
+                format!(r#"<line {stroke} x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}" />"#)
+            }
+            Element::Polyline { points } => {
+                let points: Vec<String> = points
+                    .iter()
+                    .map(|(x, y)| {
+                        format!("{},{}", (x - x_offset) * x_scale, (y - y_offset) * y_scale)
+                    })
+                    .collect();
+                let points = points.join(" ");
+                format!(r#"<polyline fill="none" {stroke} points="{points}" />"#)
+            }
+            Element::Leader { start, stop } => {
+                let (x1, y1) = (
+                    (start.0 - x_offset) * x_scale,
+                    (start.1 - y_offset) * y_scale,
+                );
+                let (x2, y2) = ((stop.0 - x_offset) * x_scale, (stop.1 - y_offset) * y_scale);
+                format!(
+                    r#"<line stroke="gray" stroke-width="1px" x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}" />"#
+                )
+            }
+            Element::Tag {
+                content,
+                x,
+                y,
+                size,
+            } => {
+                let (new_x, new_y) = ((x - x_offset) * x_scale, (y - y_offset) * y_scale);
+                // A monospace font keeps the width estimate of the label placement exact.
+                format!(
+                    r#"<text x="{new_x}" y="{new_y}" font-family="monospace" font-size="{size}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                    escape(content)
+                )
             }
-            Element::Tag { .. } => todo!(),
         }
     }
 }
 
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 impl From<&Node> for Element {
     fn from(n: &Node) -> Self {
 
         let m = *n.loc.read().unwrap();
         Element::Circle {
-            radius: n.weight ,
+            radius: n.radius(),
             x: m.x,
             y: m.y,
+            fill: n.community().map(|e| PALETTE[e % PALETTE.len()]),
         }
     }
 }
@@ -140,3 +398,55 @@ impl From<&Relation> for Element {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Element, Part, Renderer};
+
+    #[test]
+    fn highlights_path_and_dims_the_rest() {
+        let mut renderer = Renderer::new();
+        // Node 3 sits on top of node 0 without being on the path.
+        for (i, (x, y)) in [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 0.0)]
+            .into_iter()
+            .enumerate()
+        {
+            renderer.add_part(
+                Part::Node(i),
+                Element::Circle {
+                    radius: 1.0,
+                    x,
+                    y,
+                    fill: None,
+                },
+            );
+        }
+        renderer.add_part(
+            Part::Edge(1, 0),
+            Element::Line {
+                start: (10.0, 0.0),
+                stop: (0.0, 0.0),
+            },
+        );
+        renderer.add_part(
+            Part::Edge(1, 3),
+            Element::Line {
+                start: (10.0, 0.0),
+                stop: (0.0, 0.0),
+            },
+        );
+        renderer.add_part(
+            Part::Edge(1, 2),
+            Element::Line {
+                start: (10.0, 0.0),
+                stop: (10.0, 10.0),
+            },
+        );
+        renderer.highlight_path(vec![0, 1]);
+        let svg = renderer.render(100.0, 100.0);
+        assert_eq!(svg.matches("#d62728").count(), 3);
+        assert_eq!(svg.matches(r#"opacity="0.2""#).count(), 4);
+        // Highlighted elements come last, so they are drawn on top.
+        assert!(svg.rfind("opacity").unwrap() < svg.find("#d62728").unwrap());
+    }
+}
@@ -0,0 +1,678 @@
+//! Local HTTP server running a simulation in the background.
+//!
+//! | Request                       | Answer                                                      |
+//! |-------------------------------|-------------------------------------------------------------|
+//! | `GET /positions`              | [snapshot](Snapshot) of all node positions                  |
+//! | `GET /layout.svg`             | the current layout, sized by the `width` and `height` query |
+//! | `GET /params`                 | spring, coloumb, time and gravity scale                     |
+//! | `POST /params`                | sets the parameters of a JSON body like `{"spring": 0.01}`  |
+//! | `POST /pause`, `POST /resume` | stops and continues the simulation                          |
+//! | `POST /commands`              | applies [commands](crate::stream::Command), one per line    |
+//! | `GET /events`                 | Server-Sent Events, a `snapshot` followed by `delta` events |
+//!
+//! A `delta` event holds the nodes which moved noticeably since the last event sent to the same
+//! client, together with the ids of removed nodes.
+//!
+//! Request bodies of more than 1 MiB are refused with `413 Payload Too Large`. Parameters which
+//! are not positive, or negative for the gravity, and sizes which are not positive are refused
+//! with `400 Bad Request`.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use nohash_hasher::IntMap;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    sim::LayoutParams,
+    stream::{Position, Snapshot, Stream},
+};
+
+/// Shortest time between two events, also the time a paused simulation sleeps between checks.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Movement of a node below which it is left out of a delta.
+const MIN_DELTA: f32 = 0.01;
+
+/// Width and height of the SVG unless the query asks for another size.
+const DEFAULT_SIZE: f32 = 1000.0;
+
+/// Largest request body accepted, larger ones are answered with 413.
+const MAX_BODY: usize = 1 << 20;
+
+/// Changes between two events sent to a client.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Delta {
+    pub step: usize,
+    pub nodes: Vec<Position>,
+    pub removed: Vec<usize>,
+}
+
+/// Parameters as sent and received by `/params`, missing ones are left as they are.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Params {
+    spring: Option<f32>,
+    #[serde(alias = "coulomb")]
+    coloumb: Option<f32>,
+    time: Option<f32>,
+    gravity: Option<f32>,
+}
+
+impl Params {
+    fn apply(self, params: LayoutParams) -> LayoutParams {
+        LayoutParams {
+            spring_scale: self.spring.unwrap_or(params.spring_scale),
+            coloumb_scale: self.coloumb.unwrap_or(params.coloumb_scale),
+            time_delta: self.time.unwrap_or(params.time_delta),
+            gravity_scale: self.gravity.unwrap_or(params.gravity_scale),
+            ..params
+        }
+    }
+
+    /// Checks that the given values keep the simulation stable.
+    fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("spring", self.spring),
+            ("coloumb", self.coloumb),
+            ("time", self.time),
+        ];
+        for (name, value) in positive {
+            if value.is_some_and(|e| !(e.is_finite() && e > 0.0)) {
+                return Err(format!("{name} has to be a positive number"));
+            }
+        }
+        if self.gravity.is_some_and(|e| !(e.is_finite() && e >= 0.0)) {
+            return Err("gravity has to be a non-negative number".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl From<LayoutParams> for Params {
+    fn from(params: LayoutParams) -> Self {
+        Self {
+            spring: Some(params.spring_scale),
+            coloumb: Some(params.coloumb_scale),
+            time: Some(params.time_delta),
+            gravity: Some(params.gravity_scale),
+        }
+    }
+}
+
+/// Client of `/events` with the positions it knows.
+struct Subscriber {
+    sender: Sender<String>,
+    known: IntMap<usize, (f32, f32)>,
+}
+
+impl Subscriber {
+    fn new(sender: Sender<String>, snapshot: &Snapshot) -> Self {
+        Self {
+            sender,
+            known: snapshot.nodes.iter().map(|e| (e.id, (e.x, e.y))).collect(),
+        }
+    }
+
+    /// Changes since the known positions, `None` if there are none worth sending.
+    fn delta(&mut self, snapshot: &Snapshot) -> Option<Delta> {
+        let mut current: IntMap<usize, (f32, f32)> = IntMap::default();
+        let mut nodes = Vec::new();
+        for node in &snapshot.nodes {
+            current.insert(node.id, (node.x, node.y));
+            let moved = self
+                .known
+                .get(&node.id)
+                .is_none_or(|(x, y)| (node.x - x).hypot(node.y - y) >= MIN_DELTA);
+            if moved {
+                self.known.insert(node.id, (node.x, node.y));
+                nodes.push(*node);
+            }
+        }
+        let mut removed: Vec<usize> = self
+            .known
+            .keys()
+            .filter(|e| !current.contains_key(e))
+            .copied()
+            .collect();
+        removed.sort_unstable();
+        removed.iter().for_each(|e| {
+            self.known.remove(e);
+        });
+        (!nodes.is_empty() || !removed.is_empty()).then_some(Delta {
+            step: snapshot.step,
+            nodes,
+            removed,
+        })
+    }
+}
+
+struct Shared {
+    stream: Mutex<Stream>,
+    paused: AtomicBool,
+    /// Set once the simulation ended, the server stops accepting requests then.
+    stopped: AtomicBool,
+    /// Locked before `stream` where both are needed.
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl Shared {
+    fn broadcast(&self) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+        let snapshot = self.stream.lock().unwrap().snapshot();
+        subscribers.retain_mut(|e| match e.delta(&snapshot) {
+            Some(delta) => e
+                .sender
+                .send(serde_json::to_string(&delta).unwrap())
+                .is_ok(),
+            None => true,
+        });
+    }
+
+    /// Runs the simulation `steps` at a time and informs the subscribers, until the process ends.
+    ///
+    /// The stream is locked for one step at a time, so requests wait for a single step at most.
+    fn simulate(&self, steps: usize) -> io::Result<()> {
+        let mut last_update = Instant::now();
+        loop {
+            let mut ran = false;
+            for _ in 0..steps {
+                {
+                    let stream = self.stream.lock().unwrap();
+                    if self.paused.load(Ordering::Relaxed) || stream.state().nodes().is_empty() {
+                        break;
+                    }
+                    stream.state().run_n_steps(1)?;
+                }
+                ran = true;
+                // Gives waiting requests a chance to take the lock before the next step.
+                thread::yield_now();
+            }
+            if !ran {
+                thread::sleep(UPDATE_INTERVAL);
+            }
+            // Commands change positions while paused too.
+            if last_update.elapsed() >= UPDATE_INTERVAL {
+                self.broadcast();
+                last_update = Instant::now();
+            }
+        }
+    }
+}
+
+/// Request line and headers of a request, the body is read once its length is accepted.
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    length: usize,
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Malformed request line {line:?}"),
+        ));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Invalid content length")
+                })?;
+            }
+        }
+    }
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        length,
+    })
+}
+
+fn read_body<R: BufRead>(reader: &mut R, length: usize) -> io::Result<String> {
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Value of a size in the query, [`DEFAULT_SIZE`] if it is missing.
+fn query_size(query: &str, key: &str) -> Result<f32, String> {
+    let Some((_, value)) = query
+        .split('&')
+        .filter_map(|e| e.split_once('='))
+        .find(|(name, _)| *name == key)
+    else {
+        return Ok(DEFAULT_SIZE);
+    };
+    match value.parse::<f32>() {
+        Ok(size) if size.is_finite() && size > 0.0 => Ok(size),
+        _ => Err(format!("{key} has to be a positive number, not {value:?}")),
+    }
+}
+
+fn respond(
+    connection: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    write!(
+        connection,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    connection.flush()
+}
+
+/// Keeps the connection open and writes the events of one subscriber to it.
+fn events(shared: &Shared, mut connection: TcpStream) -> io::Result<()> {
+    connection.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+          Connection: keep-alive\r\n\r\n",
+    )?;
+    let (sender, receiver) = mpsc::channel();
+    let snapshot = {
+        let mut subscribers = shared.subscribers.lock().unwrap();
+        let snapshot = shared.stream.lock().unwrap().snapshot();
+        subscribers.push(Subscriber::new(sender, &snapshot));
+        snapshot
+    };
+    write!(
+        connection,
+        "event: snapshot\ndata: {}\n\n",
+        serde_json::to_string(&snapshot)?
+    )?;
+    connection.flush()?;
+    for delta in receiver {
+        write!(connection, "event: delta\ndata: {delta}\n\n")?;
+        connection.flush()?;
+    }
+    Ok(())
+}
+
+fn handle(shared: &Shared, mut connection: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(connection.try_clone()?);
+    let request = read_request(&mut reader)?;
+    if request.length > MAX_BODY {
+        let message = format!("Bodies are limited to {MAX_BODY} bytes");
+        return respond(
+            &mut connection,
+            "413 Payload Too Large",
+            "text/plain",
+            &message,
+        );
+    }
+    let body = read_body(&mut reader, request.length)?;
+    let ok = |json: String| ("200 OK", "application/json", json);
+    let bad_request = |e: &dyn std::fmt::Display| ("400 Bad Request", "text/plain", e.to_string());
+    let (status, content_type, body) = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/positions") => ok(serde_json::to_string(
+            &shared.stream.lock().unwrap().snapshot(),
+        )?),
+        ("GET", "/layout.svg") => {
+            let size = query_size(&request.query, "width")
+                .and_then(|width| Ok((width, query_size(&request.query, "height")?)));
+            match size {
+                Ok((width, height)) => {
+                    let svg = shared.stream.lock().unwrap().state().render(width, height);
+                    ("200 OK", "image/svg+xml", svg)
+                }
+                Err(e) => bad_request(&e),
+            }
+        }
+        ("GET", "/params") => {
+            let params = Params::from(shared.stream.lock().unwrap().state().params());
+            ok(serde_json::to_string(&params)?)
+        }
+        ("POST", "/params") => match serde_json::from_str::<Params>(&body)
+            .map_err(|e| e.to_string())
+            .and_then(|e| e.validate().map(|_| e))
+        {
+            Ok(changes) => {
+                let mut stream = shared.stream.lock().unwrap();
+                let params = changes.apply(stream.state().params());
+                stream.set_params(params);
+                ok(serde_json::to_string(&Params::from(params))?)
+            }
+            Err(e) => bad_request(&e),
+        },
+        ("POST", "/pause") => {
+            shared.paused.store(true, Ordering::Relaxed);
+            ok(json!({ "paused": true }).to_string())
+        }
+        ("POST", "/resume") => {
+            shared.paused.store(false, Ordering::Relaxed);
+            ok(json!({ "paused": false }).to_string())
+        }
+        // Answered like the stream command, one line per snapshot or error.
+        ("POST", "/commands") => {
+            let mut stream = shared.stream.lock().unwrap();
+            let answers: Vec<String> = body.lines().filter_map(|e| stream.apply_line(e)).collect();
+            (
+                "200 OK",
+                "application/x-ndjson",
+                answers.iter().map(|e| format!("{e}\n")).collect(),
+            )
+        }
+        ("GET", "/events") => return events(shared, connection),
+        _ => (
+            "404 Not Found",
+            "text/plain",
+            format!("No {} {}", request.method, request.path),
+        ),
+    };
+    respond(&mut connection, status, content_type, &body)
+}
+
+/// Marks the simulation as stopped when dropped, also while unwinding from a panic, and wakes
+/// the accept loop of [`Server::run`] with a connection of its own.
+struct StopOnDrop {
+    shared: Arc<Shared>,
+    address: SocketAddr,
+}
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        let mut address = self.address;
+        if address.ip().is_unspecified() {
+            address.set_ip(match address {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        let _ = TcpStream::connect(address);
+    }
+}
+
+/// HTTP server around a [`Stream`], simulated `steps` at a time in the background.
+pub struct Server {
+    listener: TcpListener,
+    shared: Arc<Shared>,
+    steps: usize,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(address: A, stream: Stream, steps: usize) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(address)?,
+            shared: Arc::new(Shared {
+                stream: Mutex::new(stream),
+                paused: AtomicBool::new(false),
+                stopped: AtomicBool::new(false),
+                subscribers: Mutex::new(Vec::new()),
+            }),
+            steps,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Starts the simulation and answers requests, each on its own thread, until an error occurs.
+    ///
+    /// Returns as soon as the simulation fails, without waiting for another request.
+    pub fn run(self) -> io::Result<()> {
+        let guard = StopOnDrop {
+            shared: Arc::clone(&self.shared),
+            address: self.local_addr()?,
+        };
+        let steps = self.steps;
+        let simulation = thread::spawn(move || guard.shared.simulate(steps));
+        for connection in self.listener.incoming() {
+            if self.shared.stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            let connection = connection?;
+            let shared = Arc::clone(&self.shared);
+            thread::spawn(move || {
+                // A client going away is no reason to stop serving the others.
+                let _ = handle(&shared, connection);
+            });
+        }
+        simulation
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("The simulation panicked")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{SocketAddr, TcpStream},
+        sync::Arc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use serde_json::Value;
+
+    use crate::{
+        builder::GraphBuilder,
+        sim::{LayoutParams, SimulationState},
+        stream::Stream,
+    };
+
+    use super::Server;
+
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> (String, String) {
+        let mut connection = TcpStream::connect(address).unwrap();
+        write!(
+            connection,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        connection.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    fn json(address: SocketAddr, method: &str, path: &str, body: &str) -> Value {
+        let (status, body) = request(address, method, path, body);
+        assert_eq!(status, "HTTP/1.1 200 OK", "{body}");
+        serde_json::from_str(&body).unwrap()
+    }
+
+    /// Asks for the positions until `condition` holds for them, failing after ten seconds.
+    fn poll_positions(address: SocketAddr, condition: impl Fn(&Value) -> bool) -> Value {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let positions = json(address, "GET", "/positions", "");
+            if condition(&positions) {
+                return positions;
+            }
+            assert!(Instant::now() < deadline, "Positions stayed at {positions}");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Data of the next event of the given kind.
+    fn next_event<R: BufRead>(events: &mut R, kind: &str) -> Value {
+        let mut current = String::new();
+        loop {
+            let mut line = String::new();
+            events.read_line(&mut line).unwrap();
+            if let Some(name) = line.strip_prefix("event: ") {
+                current = name.trim().to_string();
+            } else if let Some(data) = line.strip_prefix("data: ") {
+                if current == kind {
+                    return serde_json::from_str(data).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn serves_and_controls_simulation() {
+        let mut builder = GraphBuilder::new();
+        builder
+            .add_node_at(1, 0.0, 0.0, 1.0)
+            .unwrap()
+            .add_node_at(2, 4.0, 0.0, 1.0)
+            .unwrap()
+            .add_edge(1, 2, 1.0)
+            .unwrap();
+        let state = SimulationState::from_graph(builder.build(), LayoutParams::default());
+        let server = Server::bind("127.0.0.1:0", Stream::new(state), 10).unwrap();
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        assert_eq!(
+            json(address, "GET", "/positions", "")["nodes"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+        let params = json(address, "POST", "/params", r#"{"coulomb": 2.0}"#);
+        assert_eq!(
+            (params["coloumb"].as_f64(), params["time"].as_f64()),
+            (Some(2.0), Some(1.0))
+        );
+        for body in [
+            r#"{"speed": 2}"#,
+            r#"{"spring": -1}"#,
+            r#"{"time": 0}"#,
+            r#"{"gravity": -0.5}"#,
+        ] {
+            assert_eq!(
+                request(address, "POST", "/params", body).0,
+                "HTTP/1.1 400 Bad Request",
+                "{body}"
+            );
+        }
+        assert_eq!(
+            json(address, "POST", "/params", r#"{"gravity": 0}"#)["gravity"].as_f64(),
+            Some(0.0)
+        );
+        let mut large = TcpStream::connect(address).unwrap();
+        write!(
+            large,
+            "POST /commands HTTP/1.1\r\nContent-Length: 1000000000000\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        large.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 413 Payload Too Large"),
+            "{response}"
+        );
+
+        // The simulation is running, so it moves on while it is not paused.
+        poll_positions(address, |e| e["step"].as_u64() > Some(0));
+        json(address, "POST", "/pause", "");
+        let paused = json(address, "GET", "/positions", "")["step"].as_u64();
+
+        let mut events = BufReader::new(TcpStream::connect(address).unwrap());
+        events
+            .get_mut()
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        events
+            .get_mut()
+            .write_all(b"GET /events HTTP/1.1\r\n\r\n")
+            .unwrap();
+        assert_eq!(
+            next_event(&mut events, "snapshot")["nodes"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let (status, answers) = request(
+            address,
+            "POST",
+            "/commands",
+            concat!(
+                "{\"op\": \"add_node\", \"id\": 3, \"x\": 50, \"y\": 50}\n",
+                "{\"op\": \"remove_node\", \"id\": 2}\n",
+                "{\"op\": \"remove_node\", \"id\": 9}",
+            ),
+        );
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(answers, "{\"error\":\"node 9 does not exist\"}\n");
+        let delta = next_event(&mut events, "delta");
+        assert_eq!(delta["nodes"][0]["id"], 3);
+        assert_eq!(delta["removed"], serde_json::json!([2]));
+        // Events were sent in the meantime, but no steps.
+        assert_eq!(delta["step"].as_u64(), paused);
+        assert_eq!(
+            json(address, "GET", "/positions", "")["step"].as_u64(),
+            paused
+        );
+
+        let (status, svg) = request(address, "GET", "/layout.svg?width=200&height=100", "");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(svg.contains("<svg"));
+        for query in ["width=NaN", "height=-5", "width=inf", "width=wide"] {
+            let path = format!("/layout.svg?{query}");
+            assert_eq!(
+                request(address, "GET", &path, "").0,
+                "HTTP/1.1 400 Bad Request",
+                "{query}"
+            );
+        }
+        assert_eq!(
+            request(address, "GET", "/missing", "").0,
+            "HTTP/1.1 404 Not Found"
+        );
+
+        json(address, "POST", "/resume", "");
+        poll_positions(address, |e| e["step"].as_u64() > paused);
+        json(address, "POST", "/pause", "");
+    }
+
+    #[test]
+    fn stops_when_the_simulation_fails() {
+        let mut builder = GraphBuilder::new();
+        builder.add_node_at(1, 0.0, 0.0, 1.0).unwrap();
+        let state = SimulationState::from_graph(builder.build(), LayoutParams::default());
+        let server = Server::bind("127.0.0.1:0", Stream::new(state), 10).unwrap();
+        // A poisoned stream makes the simulation panic at its next step.
+        let shared = Arc::clone(&server.shared);
+        let _ = thread::spawn(move || {
+            let _stream = shared.stream.lock().unwrap();
+            panic!("poisoning the stream");
+        })
+        .join();
+
+        let running = thread::spawn(move || server.run());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !running.is_finished() {
+            assert!(Instant::now() < deadline, "The server kept running");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(running.join().unwrap().is_err());
+    }
+}
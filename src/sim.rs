@@ -1,31 +1,202 @@
 use std::{
-    ops::{Range, Sub},
-    sync::Arc,
+    ops::{ControlFlow, Range, Sub},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     thread::{self, available_parallelism},
+    time::{Duration, Instant},
 };
 
 use crate::{
-    model::{Coordinates, Node, Relation},
-    render::{Element, Renderer},
+    export::Layout,
+    layout::LayoutEngine,
+    model::{Coordinates, Graph, Node, Relation},
 };
 
 
 use lazy_static::lazy_static;
 
 lazy_static! {
-    static ref AVAILABLE_PARALLELISM: usize = available_parallelism().unwrap().get().sub(1).max(1);
+    pub(crate) static ref AVAILABLE_PARALLELISM: usize =
+        available_parallelism().unwrap().get().sub(1).max(1);
 }
 
 pub const SPING_SCALE: f32 = 1.0 / 200.0;
 pub const COLOUMB_SCALE: f32 = 1.0;
 pub const TIME_DELTA: f32 = 1.0;
+pub const GRAVITY_SCALE: f32 = 0.0;
+pub const COLLISION_SCALE: f32 = 0.0;
+pub const COMMUNITY_SCALE: f32 = 0.0;
+
+/// Parameters of the force directed layout.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LayoutParams {
+    pub spring_scale: f32,
+    pub coloumb_scale: f32,
+    pub time_delta: f32,
+    /// Pull towards the centre of mass, keeps disconnected components from drifting apart.
+    pub gravity_scale: f32,
+    /// Push between nodes whose circles overlap.
+    pub collision_scale: f32,
+    /// Pull between nodes of the same [community](crate::community), so clusters separate.
+    pub community_scale: f32,
+}
+
+impl Default for LayoutParams {
+    fn default() -> Self {
+        Self {
+            spring_scale: SPING_SCALE,
+            coloumb_scale: COLOUMB_SCALE,
+            time_delta: TIME_DELTA,
+            gravity_scale: GRAVITY_SCALE,
+            collision_scale: COLLISION_SCALE,
+            community_scale: COMMUNITY_SCALE,
+        }
+    }
+}
+
+/// Statistics of a single simulation step or of a batch of steps.
+#[derive(Copy, Clone, Debug)]
+pub struct StepStats {
+    /// Number of steps simulated so far, including this one.
+    pub step: usize,
+    /// Summed displacement of all nodes.
+    pub displacement: f32,
+    /// Largest displacement of a single node.
+    pub max_displacement: f32,
+    /// Time spent moving the nodes.
+    pub duration: Duration,
+    /// Potential energy after the step. Only filled in when asked for, as it costs as much as a
+    /// step.
+    pub energy: Option<f32>,
+}
+
+/// Receives the statistics of every step run by [`SimulationState::run_observed`].
+pub trait Observer {
+    fn observe(&mut self, stats: &StepStats) -> ControlFlow<()>;
+
+    /// Whether [`StepStats::energy`] should be computed for this observer.
+    fn wants_energy(&self) -> bool {
+        false
+    }
+}
+
+impl<F: FnMut(&StepStats) -> ControlFlow<()>> Observer for F {
+    fn observe(&mut self, stats: &StepStats) -> ControlFlow<()> {
+        self(stats)
+    }
+}
+
+/// Wraps an observer so it receives the energy after every step.
+pub struct WithEnergy<O>(pub O);
+
+impl<O: Observer> Observer for WithEnergy<O> {
+    fn observe(&mut self, stats: &StepStats) -> ControlFlow<()> {
+        self.0.observe(stats)
+    }
+
+    fn wants_energy(&self) -> bool {
+        true
+    }
+}
+
+/// Iterator returned by [`SimulationState::steps`].
+pub struct Steps<'a> {
+    state: &'a SimulationState,
+}
+
+impl Iterator for Steps<'_> {
+    type Item = std::io::Result<StepStats>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.state.step())
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Displacement {
+    total: f32,
+    max: f32,
+}
+
+pub(crate) fn split_ranges(nodes_len: usize) -> Vec<Range<usize>> {
+    let thread_nums = *AVAILABLE_PARALLELISM;
+    let slice_len = nodes_len / thread_nums;
+    (0..thread_nums)
+        .map(|e| {
+            if e == 0 {
+                0..slice_len
+            } else if e == thread_nums - 1 {
+                (slice_len * e)..nodes_len
+            } else {
+                (e * slice_len)..((e + 1) * slice_len)
+            }
+        })
+        .collect()
+}
+
+/// Splits `0..len` across the available threads and runs `f` on every part in parallel.
+pub(crate) fn map_ranges<T, F>(len: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(Range<usize>) -> T + Sync,
+{
+    crossbeam::scope(|scope| {
+        let handles: Vec<_> = split_ranges(len)
+            .into_iter()
+            .map(|range| {
+                let f = &f;
+                scope.spawn(move |_| f(range))
+            })
+            .collect();
+        handles.into_iter().map(|e| e.join().unwrap()).collect()
+    })
+    .unwrap()
+}
+
+/// Moves the nodes in `range` for `n` steps, the other nodes only act on them.
+fn simulate_range(
+    nodes: &[Arc<Node>],
+    range: Range<usize>,
+    n: usize,
+    params: &LayoutParams,
+) -> Displacement {
+    let mut total_length = 0.0;
+    let mut max_length: f32 = 0.0;
+    let mut new_coordinates: Vec<Coordinates> = Vec::new();
+    for _ in 0..n {
+        let centre = Node::centre(nodes);
+        let iterator = nodes[range.clone()]
+            .iter()
+            .map(|e| (e, *e.loc.read().unwrap()))
+            .map(|(e, c)| (e.calc_new_position(nodes, centre, params), c))
+            .map(|(new, old)| (new, new.to(old).length()))
+            .map(|(new, length)| {
+                if length.is_normal() {
+                    total_length += length.abs();
+                    max_length = max_length.max(length.abs());
+                }
+                new
+            });
+        new_coordinates.clear();
+        new_coordinates.extend(iterator);
+        nodes[range.clone()]
+            .iter()
+            .zip(new_coordinates.iter())
+            .for_each(|(n, c)| n.update_coordinates(*c));
+    }
+    Displacement {
+        total: total_length,
+        max: max_length,
+    }
+}
 
 pub struct SimulationState {
     nodes: Arc<Vec<Arc<Node>>>,
     relations: Arc<Vec<Arc<Relation>>>,
-    spring_scale: f32,
-    coloumb_scale: f32,
-    time_delta: f32,
+    params: LayoutParams,
+    steps_done: AtomicUsize,
 }
 
 impl SimulationState {
@@ -39,97 +210,383 @@ impl SimulationState {
         Self {
             nodes: Arc::new(nodes),
             relations: Arc::new(relations),
-            spring_scale,
-            coloumb_scale,
-            time_delta,
+            params: LayoutParams {
+                spring_scale,
+                coloumb_scale,
+                time_delta,
+                ..LayoutParams::default()
+            },
+            steps_done: AtomicUsize::new(0),
         }
     }
 
-    fn run_simulation_step(&self, n: usize) -> std::io::Result<f32> {
-        let thread_nums = *AVAILABLE_PARALLELISM;
-        let nodes_len = self.nodes.len();
-        let slice_len = nodes_len / thread_nums;
-        let ranges: Vec<Range<usize>> = (0..thread_nums)
-            .map(|e| {
-                if e == 0 {
-                    0..slice_len
-                } else if e == thread_nums - 1 {
-                    (slice_len * e)..nodes_len
-                } else {
-                    (e * slice_len)..((e + 1) * slice_len)
-                }
-            })
-            .collect();
+    pub fn with_gravity(mut self, gravity_scale: f32) -> Self {
+        self.params.gravity_scale = gravity_scale;
+        self
+    }
+
+    pub fn with_collision(mut self, collision_scale: f32) -> Self {
+        self.params.collision_scale = collision_scale;
+        self
+    }
+
+    pub fn with_community(mut self, community_scale: f32) -> Self {
+        self.params.community_scale = community_scale;
+        self
+    }
+
+    pub fn from_graph(graph: Graph, params: LayoutParams) -> Self {
+        Self::new(
+            graph.nodes,
+            graph.relations,
+            params.spring_scale,
+            params.coloumb_scale,
+            params.time_delta,
+        )
+        .with_gravity(params.gravity_scale)
+        .with_collision(params.collision_scale)
+        .with_community(params.community_scale)
+    }
+
+    pub fn params(&self) -> LayoutParams {
+        self.params
+    }
+
+    /// Changes the parameters used by the following steps.
+    pub fn set_params(&mut self, params: LayoutParams) {
+        self.params = params;
+    }
+
+    pub fn nodes(&self) -> &[Arc<Node>] {
+        &self.nodes
+    }
+
+    pub fn relations(&self) -> &[Arc<Relation>] {
+        &self.relations
+    }
+
+    /// Nodes to change the graph between steps. Relations of removed nodes have to be removed too.
+    pub fn nodes_mut(&mut self) -> &mut Vec<Arc<Node>> {
+        Arc::make_mut(&mut self.nodes)
+    }
+
+    /// Relations to change the graph between steps, added ones have to be created by
+    /// [`Relation::connect`].
+    pub fn relations_mut(&mut self) -> &mut Vec<Arc<Relation>> {
+        Arc::make_mut(&mut self.relations)
+    }
+
+    fn run_simulation_step(&self, n: usize) -> std::io::Result<Displacement> {
+        let ranges = split_ranges(self.nodes.len());
 
         let mut handles = Vec::new();
-        let spring_scale = self.spring_scale;
-        let coloumb_scale = self.coloumb_scale;
-        let time_delta = self.time_delta;
+        let params = self.params;
 
         for range in ranges {
             let local_nodes = Arc::clone(&self.nodes);
-            let handle = thread::spawn(move || {
-                let mut total_length = 0.0;
-                let mut new_coordinates: Vec<Coordinates> = Vec::new();
-                for _ in 0..n {
-                    let iterator = local_nodes[range.clone()]
-                        .iter()
-                        .map(|e| (e, *e.loc.read().unwrap()))
-                        .map(|(e, c)| {
-                            (
-                                e.calc_new_position(
-                                    &local_nodes,
-                                    spring_scale,
-                                    coloumb_scale,
-                                    time_delta,
-                                ),
-                                c,
-                            )
-                        })
-                        .map(|(new, old)| (new, new.to(old).length()))
-                        .map(|(new, length)| {
-                            if length.is_normal() {
-                                total_length += length.abs();
-                            }
-                            new
-                        });
-                    new_coordinates.clear();
-                    new_coordinates.extend(iterator);
-                    local_nodes[range.clone()]
-                        .iter()
-                        .zip(new_coordinates.iter())
-                        .for_each(|(n, c)| n.update_coordinates(*c));
-                }
-                total_length
-            });
+            let handle = thread::spawn(move || simulate_range(&local_nodes, range, n, &params));
             handles.push(handle);
         }
 
-        let mut change = 0.0;
+        let mut change = Displacement::default();
 
         for handle in handles {
-            change += handle.join().unwrap();
+            let displacement = handle.join().unwrap();
+            change.total += displacement.total;
+            change.max = change.max.max(displacement.max);
         }
 
+        self.steps_done.fetch_add(n, Ordering::Relaxed);
+
         Ok(change)
     }
 
     pub fn run_n_steps(&self, n: usize) -> std::io::Result<f32> {
-        self.run_simulation_step(n)
+        Ok(self.run_simulation_step(n)?.total)
     }
 
-    pub fn render(self, x: f32, y: f32) -> String {
-        let mut renderer = Renderer::new();
-        self.nodes
-            .iter()
-            .map(|e| Element::from(e.as_ref()))
-            .for_each(|e| renderer.add_element(e));
+    /// Same as [`Self::run_n_steps`], but moves all nodes on the calling thread.
+    ///
+    /// Meant for small graphs, where spawning the worker threads costs more than the step.
+    pub(crate) fn run_n_steps_serial(&self, n: usize) -> f32 {
+        let displacement = simulate_range(&self.nodes, 0..self.nodes.len(), n, &self.params);
+        self.steps_done.fetch_add(n, Ordering::Relaxed);
+        displacement.total
+    }
 
-        self.relations
-            .iter()
-            .map(|e| Element::from(e.as_ref()))
-            .for_each(|e| renderer.add_element(e));
+    /// Advances the simulation by a single step.
+    pub fn step(&self) -> std::io::Result<StepStats> {
+        self.run_batch(1)
+    }
+
+    /// Advances the simulation by `n` steps, returning statistics accumulated over the batch.
+    ///
+    /// The energy is left out, it costs as much as a step. Use [`Self::run_batch_with_energy`]
+    /// where it is needed.
+    pub fn run_batch(&self, n: usize) -> std::io::Result<StepStats> {
+        let start = Instant::now();
+        let displacement = self.run_simulation_step(n)?;
+        let duration = start.elapsed();
+        Ok(StepStats {
+            step: self.steps_done(),
+            displacement: displacement.total,
+            max_displacement: displacement.max,
+            duration,
+            energy: None,
+        })
+    }
+
+    /// Same as [`Self::run_batch`], but also computes the energy after the batch.
+    pub fn run_batch_with_energy(&self, n: usize) -> std::io::Result<StepStats> {
+        let mut stats = self.run_batch(n)?;
+        stats.energy = Some(self.energy());
+        Ok(stats)
+    }
+
+    /// Runs at most `n` single steps, handing the statistics of each one to the observer.
+    ///
+    /// Stops early once the observer returns [`ControlFlow::Break`]. The energy is only computed
+    /// if the observer [wants it](Observer::wants_energy), e.g. when wrapped in [`WithEnergy`].
+    /// Returns the statistics of the last step that was run.
+    pub fn run_observed<O: Observer>(
+        &self,
+        n: usize,
+        mut observer: O,
+    ) -> std::io::Result<Option<StepStats>> {
+        let mut last = None;
+        for _ in 0..n {
+            let stats = if observer.wants_energy() {
+                self.run_batch_with_energy(1)?
+            } else {
+                self.step()?
+            };
+            last = Some(stats);
+            if observer.observe(&stats).is_break() {
+                break;
+            }
+        }
+        Ok(last)
+    }
+
+    /// Endless iterator advancing the simulation by one step per item.
+    pub fn steps(&self) -> Steps<'_> {
+        Steps { state: self }
+    }
+
+    /// Number of steps simulated so far.
+    pub fn steps_done(&self) -> usize {
+        self.steps_done.load(Ordering::Relaxed)
+    }
 
-        renderer.render(x, y)
+    /// Total potential energy of the current layout.
+    pub fn energy(&self) -> f32 {
+        let params = self.params;
+        let centre = Node::centre(&self.nodes);
+
+        let handles: Vec<_> = split_ranges(self.nodes.len())
+            .into_iter()
+            .map(|range| {
+                let local_nodes = Arc::clone(&self.nodes);
+                thread::spawn(move || {
+                    local_nodes[range]
+                        .iter()
+                        .map(|e| e.potential_energy(&local_nodes, centre, &params))
+                        .sum::<f32>()
+                })
+            })
+            .collect();
+
+        handles.into_iter().map(|e| e.join().unwrap()).sum()
+    }
+
+    pub fn layout(&self) -> Layout {
+        LayoutEngine::layout(self)
+    }
+
+    pub fn render(&self, x: f32, y: f32) -> String {
+        LayoutEngine::render(self, x, y)
+    }
+}
+
+impl LayoutEngine for SimulationState {
+    /// Runs `iterations` simulation steps and returns the summed displacement.
+    fn run(&self, iterations: usize) -> std::io::Result<f32> {
+        self.run_n_steps(iterations)
+    }
+
+    fn nodes(&self) -> &[Arc<Node>] {
+        &self.nodes
+    }
+
+    fn relations(&self) -> &[Arc<Relation>] {
+        &self.relations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use crate::builder::GraphBuilder;
+
+    use super::{LayoutParams, SimulationState, WithEnergy};
+
+    fn pair(distance: f32) -> SimulationState {
+        let mut builder = GraphBuilder::new();
+        builder
+            .add_node_at(1, 0.0, 0.0, 1.0)
+            .unwrap()
+            .add_node_at(2, distance, 0.0, 2.0)
+            .unwrap()
+            .add_edge(1, 2, 1.0)
+            .unwrap();
+        let params = LayoutParams {
+            spring_scale: 1.0,
+            coloumb_scale: 1.0,
+            time_delta: 0.1,
+            ..LayoutParams::default()
+        };
+        SimulationState::from_graph(builder.build(), params)
+    }
+
+    #[test]
+    fn energy_of_pair() {
+        let state = pair(2.0);
+        // coloumb: 1 * 2 / 2, spring: 0.5 * 2 * 2
+        let energy = state.energy();
+        assert!((energy - 3.0).abs() < 1.0E-5, "Energy is {energy}");
+    }
+
+    #[test]
+    fn observer_stops_early() {
+        let state = pair(2.0);
+        let mut seen = Vec::new();
+        let last = state
+            .run_observed(10, |stats: &super::StepStats| {
+                seen.push(stats.step);
+                if stats.step == 3 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(seen, [1, 2, 3]);
+        assert_eq!(last.step, 3);
+        assert_eq!(last.energy, None);
+        assert_eq!(state.steps_done(), 3);
+
+        let stats = state.steps().take(2).last().unwrap().unwrap();
+        assert_eq!(stats.step, 5);
+        assert!(stats.max_displacement <= stats.displacement);
+    }
+
+    #[test]
+    fn observer_asks_for_energy() {
+        let state = pair(2.0);
+        let mut energies = Vec::new();
+        let observer = WithEnergy(|stats: &super::StepStats| {
+            energies.push(stats.energy);
+            ControlFlow::Continue(())
+        });
+        let last = state.run_observed(2, observer).unwrap().unwrap();
+        assert!(energies.iter().all(Option::is_some));
+        assert_eq!(last.energy, Some(state.energy()));
+    }
+
+    #[test]
+    fn gravity_keeps_components_together() {
+        let distance = |gravity_scale: f32| {
+            let mut builder = GraphBuilder::new();
+            builder
+                .add_node_at(1, 0.0, 0.0, 1.0)
+                .unwrap()
+                .add_node_at(2, 1.0, 0.0, 1.0)
+                .unwrap();
+            let params = LayoutParams {
+                gravity_scale,
+                ..LayoutParams::default()
+            };
+            let state = SimulationState::from_graph(builder.build(), params);
+            state.run_n_steps(2000).unwrap();
+            let layout = state.layout();
+            let (a, b) = (&layout.nodes[0], &layout.nodes[1]);
+            (a.x - b.x).hypot(a.y - b.y)
+        };
+        // Without gravity the pair drifts apart. With it they settle where the repulsion 1 / d²
+        // matches the pull 0.5 * d / 2 towards the centre between them.
+        let settled = distance(0.5);
+        assert!(distance(0.0) > 10.0);
+        assert!(
+            (settled - 4.0f32.cbrt()).abs() < 0.05,
+            "Distance is {settled}"
+        );
+    }
+
+    #[test]
+    fn collision_separates_circles() {
+        let params = LayoutParams {
+            coloumb_scale: 0.0,
+            collision_scale: 0.5,
+            ..LayoutParams::default()
+        };
+        let distance = |radius: Option<f32>| {
+            let mut builder = GraphBuilder::new();
+            builder
+                .add_node_at(1, 0.0, 0.0, 1.0)
+                .unwrap()
+                .add_node_at(2, 0.5, 0.0, 1.0)
+                .unwrap();
+            let graph = builder.build();
+            graph.nodes.iter().for_each(|e| e.set_radius(radius));
+            let state = SimulationState::from_graph(graph, params);
+            state.run_n_steps(200).unwrap();
+            let layout = state.layout();
+            let (a, b) = (&layout.nodes[0], &layout.nodes[1]);
+            (a.x - b.x).hypot(a.y - b.y)
+        };
+        let distance_by_weight = distance(None);
+        assert!(
+            distance_by_weight > 1.99,
+            "Distance is {distance_by_weight}"
+        );
+        // Circles with a radius of their own collide by that radius.
+        let distance_by_radius = distance(Some(2.0));
+        assert!(
+            distance_by_radius > 3.99,
+            "Distance is {distance_by_radius}"
+        );
+    }
+
+    #[test]
+    fn community_attraction_groups_members() {
+        let mut builder = GraphBuilder::new();
+        for (id, x, y) in [(1, 0.0, 0.0), (2, 4.0, 0.0), (3, 2.0, 1.0)] {
+            builder.add_node_at(id, x, y, 1.0).unwrap();
+        }
+        let graph = builder.build();
+        graph.nodes[0].set_community(Some(0));
+        graph.nodes[1].set_community(Some(0));
+        graph.nodes[2].set_community(Some(1));
+        let params = LayoutParams {
+            community_scale: 0.5,
+            time_delta: 0.1,
+            ..LayoutParams::default()
+        };
+        let state = SimulationState::from_graph(graph, params);
+        state.run_n_steps(500).unwrap();
+        let layout = state.layout();
+        let distance = |a: usize, b: usize| {
+            let (a, b) = (&layout.nodes[a], &layout.nodes[b]);
+            (a.x - b.x).hypot(a.y - b.y)
+        };
+        // The members of community 0 pull together and push the other node out from between them.
+        assert!(
+            distance(0, 1) < distance(0, 2),
+            "{} {}",
+            distance(0, 1),
+            distance(0, 2)
+        );
     }
 }
@@ -0,0 +1,337 @@
+//! Transforms shrinking a graph before it is laid out.
+//!
+//! Relation weights are treated as spring stiffness here, like in the
+//! [simulation](crate::sim): a chain of relations becomes one relation as soft as the springs in
+//! series, parallel relations become one as stiff as the springs side by side.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use nohash_hasher::IntMap;
+
+use crate::{
+    adjacency::Adjacency,
+    builder::{GraphBuilder, GraphError},
+    export::{Layout, LayoutEdge, LayoutNode},
+    layout::{read_positions, write_positions},
+    model::{Graph, Node},
+};
+
+/// Attribute of a meta-node holding the number of nodes it stands for.
+pub const MEMBERS: &str = "members";
+
+/// Nodes of degree two between two other nodes, replaced by a single relation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chain {
+    pub from: usize,
+    pub to: usize,
+    /// Ids of the removed nodes in the order from `from` to `to`.
+    pub inner: Vec<usize>,
+}
+
+/// Result of [`contract_chains`].
+pub struct Contraction {
+    /// The graph to lay out, with fresh nodes.
+    pub graph: Graph,
+    pub chains: Vec<Chain>,
+}
+
+impl Contraction {
+    /// Number of nodes removed by the contraction.
+    pub fn removed(&self) -> usize {
+        self.chains.iter().map(|e| e.inner.len()).sum()
+    }
+
+    /// Moves the nodes of `original` to the positions of the laid out graph, the removed nodes
+    /// evenly spaced along the relation that replaced their chain.
+    pub fn expand(&self, original: &Graph) {
+        let laid_out: IntMap<usize, (f32, f32)> = self
+            .graph
+            .nodes
+            .iter()
+            .map(|e| e.id())
+            .zip(read_positions(&self.graph.nodes))
+            .collect();
+        let mut positions = laid_out.clone();
+        for chain in &self.chains {
+            let (from, to) = (laid_out[&chain.from], laid_out[&chain.to]);
+            let steps = (chain.inner.len() + 1) as f32;
+            for (k, id) in chain.inner.iter().enumerate() {
+                let t = (k + 1) as f32 / steps;
+                positions.insert(
+                    *id,
+                    (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t),
+                );
+            }
+        }
+        let moved: Vec<(f32, f32)> = original
+            .nodes
+            .iter()
+            .zip(read_positions(&original.nodes))
+            .map(|(node, current)| positions.get(&node.id()).copied().unwrap_or(current))
+            .collect();
+        write_positions(&original.nodes, &moved);
+    }
+}
+
+/// Builds a graph from `layout` whose nodes keep the state `original` nodes of the same id had
+/// beyond what the layout holds.
+fn rebuild(layout: &Layout, original: &[Arc<Node>]) -> Result<Graph, GraphError> {
+    let graph = GraphBuilder::from_layout(layout)?.build();
+    let by_id: IntMap<usize, &Arc<Node>> = original.iter().map(|e| (e.id(), e)).collect();
+    for node in &graph.nodes {
+        if let Some(old) = by_id.get(&node.id()) {
+            node.set_anchor(old.anchor());
+            node.set_community(old.community());
+        }
+    }
+    Ok(graph)
+}
+
+/// Replaces every chain of nodes with exactly two neighbours by one relation between the nodes at
+/// its ends. Chains closing a cycle on one end node, and cycles made of such nodes only, are kept.
+/// Relations of weight zero are no springs at all, so a node at one of them ends a chain.
+pub fn contract_chains(graph: &Graph) -> Result<Contraction, GraphError> {
+    let adjacency = Adjacency::from_graph(&graph.nodes, &graph.relations);
+    let inner = |i: usize| match adjacency.neighbours(i) {
+        [(a, wa), (b, wb)] => a != b && *a != i && *b != i && *wa != 0.0 && *wb != 0.0,
+        _ => false,
+    };
+    let n = adjacency.len();
+    let mut removed = vec![false; n];
+    let mut visited = vec![false; n];
+    let mut chains = Vec::new();
+    let mut edges = Vec::new();
+    for start in (0..n).filter(|e| !inner(*e)) {
+        for &(first, weight) in adjacency.neighbours(start) {
+            if !inner(first) || visited[first] {
+                continue;
+            }
+            let (mut previous, mut current) = (start, first);
+            let mut members = Vec::new();
+            let mut compliance = 1.0 / weight.abs();
+            while inner(current) {
+                visited[current] = true;
+                members.push(current);
+                let &(next, weight) = adjacency
+                    .neighbours(current)
+                    .iter()
+                    .find(|(e, _)| *e != previous)
+                    .unwrap();
+                compliance += 1.0 / weight.abs();
+                previous = current;
+                current = next;
+            }
+            if current == start {
+                continue;
+            }
+            members.iter().for_each(|e| removed[*e] = true);
+            edges.push(LayoutEdge {
+                from: graph.nodes[start].id(),
+                to: graph.nodes[current].id(),
+                weight: compliance.recip(),
+                bends: Vec::new(),
+            });
+            chains.push(Chain {
+                from: graph.nodes[start].id(),
+                to: graph.nodes[current].id(),
+                inner: members.iter().map(|e| graph.nodes[*e].id()).collect(),
+            });
+        }
+    }
+
+    let layout = Layout::from_graph(&graph.nodes, &graph.relations);
+    let index = layout.index();
+    let contracted = Layout {
+        nodes: layout
+            .nodes
+            .iter()
+            .zip(&removed)
+            .filter(|(_, removed)| !**removed)
+            .map(|(e, _)| e.clone())
+            .collect(),
+        edges: layout
+            .edges
+            .iter()
+            .filter(|e| !removed[index[&e.from]] && !removed[index[&e.to]])
+            .cloned()
+            .chain(edges)
+            .collect(),
+        path: Vec::new(),
+    };
+    Ok(Contraction {
+        graph: rebuild(&contracted, &graph.nodes)?,
+        chains,
+    })
+}
+
+/// Merges all nodes sharing a value of `attribute` into one meta-node.
+///
+/// A meta-node sits at the centre of its members, is as heavy as all of them together and is
+/// labelled with the value. Relations inside a group disappear, relations between a meta-node
+/// and another node are merged into one. Nodes without the attribute stay as they are.
+pub fn collapse(graph: &Graph, attribute: &str) -> Result<Graph, GraphError> {
+    let layout = Layout::from_graph(&graph.nodes, &graph.relations);
+    let mut groups: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, node) in layout.nodes.iter().enumerate() {
+        if let Some(value) = node.attributes.get(attribute) {
+            groups.entry(value).or_default().push(i);
+        }
+    }
+    let next_id = layout.nodes.iter().map(|e| e.id + 1).max().unwrap_or(0);
+    // Id every node ends up as.
+    let mut target: Vec<usize> = layout.nodes.iter().map(|e| e.id).collect();
+    let mut nodes: Vec<LayoutNode> = layout
+        .nodes
+        .iter()
+        .filter(|e| !e.attributes.contains_key(attribute))
+        .cloned()
+        .collect();
+    for (k, (value, members)) in groups.iter().enumerate() {
+        let id = next_id + k;
+        let count = members.len() as f32;
+        let mut meta = LayoutNode {
+            id,
+            label: Some(value.to_string()),
+            x: members.iter().map(|e| layout.nodes[*e].x).sum::<f32>() / count,
+            y: members.iter().map(|e| layout.nodes[*e].y).sum::<f32>() / count,
+            weight: members.iter().map(|e| layout.nodes[*e].weight).sum(),
+            radius: None,
+            attributes: BTreeMap::new(),
+        };
+        meta.attributes
+            .insert(attribute.to_string(), value.to_string());
+        meta.attributes
+            .insert(MEMBERS.to_string(), members.len().to_string());
+        members.iter().for_each(|e| target[*e] = id);
+        nodes.push(meta);
+    }
+
+    let index = layout.index();
+    let mut merged: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    let mut edges: Vec<LayoutEdge> = Vec::new();
+    for edge in &layout.edges {
+        let (from, to) = (target[index[&edge.from]], target[index[&edge.to]]);
+        if from == to && from >= next_id {
+            continue;
+        }
+        let involves_meta = from >= next_id || to >= next_id;
+        let key = (from.min(to), from.max(to));
+        match merged.get(&key) {
+            Some(&i) if involves_meta => edges[i].weight += edge.weight,
+            _ => {
+                if involves_meta {
+                    merged.insert(key, edges.len());
+                }
+                edges.push(LayoutEdge {
+                    from,
+                    to,
+                    weight: edge.weight,
+                    bends: Vec::new(),
+                });
+            }
+        }
+    }
+    rebuild(
+        &Layout {
+            nodes,
+            edges,
+            path: Vec::new(),
+        },
+        &graph.nodes,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::GraphBuilder,
+        layout::read_positions,
+        model::{Coordinates, Graph},
+    };
+
+    use super::{collapse, contract_chains, Chain};
+
+    /// A triangle 0 - 1 - 2 with a line 2 - 3 - 4 - 5 leaving it.
+    fn graph() -> Graph {
+        line_graph(2.0)
+    }
+
+    fn line_graph(middle: f32) -> Graph {
+        let mut builder = GraphBuilder::new();
+        for i in 0..6 {
+            builder
+                .add_node_at(i, i as f32, 0.0, 1.0)
+                .unwrap()
+                .attribute(i, "line", if i < 3 { "ring" } else { "branch" })
+                .unwrap();
+        }
+        for (from, to, weight) in [
+            (0, 1, 1.0),
+            (1, 2, 1.0),
+            (2, 0, 1.0),
+            (2, 3, 2.0),
+            (3, 4, middle),
+            (4, 5, 1.0),
+        ] {
+            builder.add_edge(from, to, weight).unwrap();
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn contracts_and_expands_chains() {
+        let original = graph();
+        original.nodes[0].set_radius(Some(4.0));
+        let contraction = contract_chains(&original).unwrap();
+        // 0 and 1 only close the triangle back on 2, so only the line is contracted.
+        assert_eq!(
+            contraction.chains,
+            vec![Chain {
+                from: 2,
+                to: 5,
+                inner: vec![3, 4]
+            }]
+        );
+        let ids: Vec<usize> = contraction.graph.nodes.iter().map(|e| e.id()).collect();
+        assert_eq!(ids, vec![0, 1, 2, 5]);
+        // Radii set apart from the weight are kept, the others still follow the weight.
+        assert_eq!(contraction.graph.nodes[0].radius_override(), Some(4.0));
+        assert_eq!(contraction.graph.nodes[1].radius_override(), None);
+        // Springs of stiffness 2, 2 and 1 in series.
+        let weight = contraction.graph.relations.last().unwrap().weight;
+        assert!((weight - 0.5).abs() < 1.0E-6);
+
+        contraction.graph.nodes[3].update_coordinates(Coordinates { x: 8.0, y: 6.0 });
+        contraction.expand(&original);
+        let positions = read_positions(&original.nodes);
+        assert_eq!(positions[3], (4.0, 2.0));
+        assert_eq!(positions[4], (6.0, 4.0));
+        assert_eq!(positions[5], (8.0, 6.0));
+    }
+
+    #[test]
+    fn keeps_chains_of_relations_without_weight() {
+        let contraction = contract_chains(&line_graph(0.0)).unwrap();
+        assert!(contraction.chains.is_empty());
+        assert!(contraction
+            .graph
+            .relations
+            .iter()
+            .all(|e| e.weight.is_finite()));
+    }
+
+    #[test]
+    fn collapses_attribute_into_meta_nodes() {
+        let collapsed = collapse(&graph(), "line").unwrap();
+        assert_eq!(collapsed.nodes.len(), 2);
+        // Groups are numbered by value after the largest id: branch first, then ring.
+        let branch = &collapsed.nodes[0];
+        assert_eq!((branch.id(), branch.label.as_deref()), (6, Some("branch")));
+        assert_eq!(branch.weight, 3.0);
+        assert_eq!(branch.attributes["members"], "3");
+        assert_eq!(branch.loc.read().unwrap().x, 4.0);
+        // Only the relation 2 - 3 crosses between the groups.
+        assert_eq!(collapsed.relations.len(), 1);
+        assert_eq!(collapsed.relations[0].weight, 2.0);
+    }
+}
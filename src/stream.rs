@@ -0,0 +1,429 @@
+//! Changes to a running simulation, read as one JSON command per line.
+//!
+//! Every command is an object whose `op` names it, like
+//! `{"op": "add_edge", "from": 1, "to": 2, "weight": 0.5}`. Commands are applied between the
+//! simulation steps, `snapshot` writes the positions of all nodes as one line of JSON and
+//! invalid commands are answered by a line like `{"error": "node 7 does not exist"}`.
+
+use std::{
+    io::{self, BufRead, Write},
+    sync::{
+        mpsc::{self, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use nohash_hasher::IntMap;
+use rand::{rngs::SmallRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    builder::{check_node_weight, check_relation, check_weight, generate_coordinate, GraphError},
+    layout::node_index,
+    model::{Coordinates, Node, Relation},
+    sim::{LayoutParams, SimulationState},
+};
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Command {
+    /// Adds a node, placed randomly like the nodes read from files unless a position is given.
+    AddNode {
+        id: usize,
+        #[serde(default)]
+        x: Option<f32>,
+        #[serde(default)]
+        y: Option<f32>,
+        #[serde(default = "one")]
+        weight: f32,
+        #[serde(default)]
+        label: Option<String>,
+    },
+    /// Removes a node together with its relations.
+    RemoveNode {
+        id: usize,
+    },
+    AddEdge {
+        from: usize,
+        to: usize,
+        #[serde(default = "one")]
+        weight: f32,
+    },
+    /// Removes all relations between two nodes, in either direction.
+    RemoveEdge {
+        from: usize,
+        to: usize,
+    },
+    /// Changes the weight of all relations between two nodes, in either direction.
+    SetWeight {
+        from: usize,
+        to: usize,
+        weight: f32,
+    },
+    /// Keeps a node where it is, or moves it to the given position first.
+    Pin {
+        id: usize,
+        #[serde(default)]
+        x: Option<f32>,
+        #[serde(default)]
+        y: Option<f32>,
+    },
+    Unpin {
+        id: usize,
+    },
+    /// Runs a number of steps before the next command, for scripts which need a certain result.
+    Step {
+        count: usize,
+    },
+    Snapshot,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Position {
+    pub id: usize,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Positions of all nodes after a number of steps.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Snapshot {
+    pub step: usize,
+    pub nodes: Vec<Position>,
+}
+
+/// A simulation together with what is needed to change it by [commands](Command).
+pub struct Stream {
+    state: SimulationState,
+    /// Position of every node in the state by its id.
+    index: IntMap<usize, usize>,
+    rng: SmallRng,
+}
+
+impl Stream {
+    pub fn new(state: SimulationState) -> Self {
+        Self {
+            index: node_index(state.nodes()),
+            state,
+            rng: SmallRng::from_seed([0u8; 32]),
+        }
+    }
+
+    pub fn state(&self) -> &SimulationState {
+        &self.state
+    }
+
+    pub fn set_params(&mut self, params: LayoutParams) {
+        self.state.set_params(params);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            step: self.state.steps_done(),
+            nodes: self
+                .state
+                .nodes()
+                .iter()
+                .map(|e| {
+                    let loc = *e.loc.read().unwrap();
+                    Position {
+                        id: e.id(),
+                        x: loc.x,
+                        y: loc.y,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn node(&self, id: usize) -> Result<&Arc<Node>, GraphError> {
+        match self.index.get(&id) {
+            Some(&i) => Ok(&self.state.nodes()[i]),
+            None => Err(GraphError::UnknownNode(id)),
+        }
+    }
+
+    /// Relations between two nodes, in either direction.
+    fn between(&self, a: usize, b: usize) -> Result<Vec<Arc<Relation>>, GraphError> {
+        self.node(b)?;
+        let found: Vec<Arc<Relation>> = self
+            .node(a)?
+            .relations()
+            .into_iter()
+            .filter(|e| (e.from.id(), e.to.id()) == (a, b) || (e.from.id(), e.to.id()) == (b, a))
+            .collect();
+        if found.is_empty() {
+            return Err(GraphError::UnknownRelation(a, b));
+        }
+        Ok(found)
+    }
+
+    /// Takes relations out of the simulation, together with the references their nodes hold.
+    fn remove_relations(&mut self, removed: &[Arc<Relation>]) {
+        removed.iter().for_each(|e| e.unregister());
+        self.state
+            .relations_mut()
+            .retain(|e| !removed.iter().any(|r| Arc::ptr_eq(e, r)));
+    }
+
+    /// Applies a command, returning the snapshot it asked for.
+    pub fn apply(&mut self, command: Command) -> io::Result<Option<Snapshot>> {
+        match command {
+            Command::AddNode {
+                id,
+                x,
+                y,
+                weight,
+                label,
+            } => {
+                check_node_weight(weight)?;
+                if self.index.contains_key(&id) {
+                    return Err(GraphError::DuplicateNode(id).into());
+                }
+                let x = x.unwrap_or_else(|| generate_coordinate(&mut self.rng));
+                let y = y.unwrap_or_else(|| generate_coordinate(&mut self.rng));
+                let node = Node::new(id, x, y, weight).with_label(label);
+                self.index.insert(id, self.state.nodes().len());
+                self.state.nodes_mut().push(Arc::new(node));
+            }
+            Command::RemoveNode { id } => {
+                let relations = self.node(id)?.relations();
+                self.remove_relations(&relations);
+                self.state.nodes_mut().remove(self.index[&id]);
+                self.index = node_index(self.state.nodes());
+            }
+            Command::AddEdge { from, to, weight } => {
+                check_relation(from, to, weight)?;
+                let relation = Relation::connect(
+                    weight,
+                    Arc::clone(self.node(from)?),
+                    Arc::clone(self.node(to)?),
+                );
+                self.state.relations_mut().push(relation);
+            }
+            Command::RemoveEdge { from, to } => {
+                let found = self.between(from, to)?;
+                self.remove_relations(&found);
+            }
+            Command::SetWeight { from, to, weight } => {
+                check_weight(weight)?;
+                let found = self.between(from, to)?;
+                self.remove_relations(&found);
+                let relations = self.state.relations_mut();
+                for old in found {
+                    relations.push(Relation::connect(
+                        weight,
+                        Arc::clone(&old.from),
+                        Arc::clone(&old.to),
+                    ));
+                }
+            }
+            Command::Pin { id, x, y } => {
+                let node = self.node(id)?;
+                let loc = *node.loc.read().unwrap();
+                node.update_coordinates(Coordinates {
+                    x: x.unwrap_or(loc.x),
+                    y: y.unwrap_or(loc.y),
+                });
+                node.set_pinned(true);
+            }
+            Command::Unpin { id } => self.node(id)?.set_pinned(false),
+            Command::Step { count } => {
+                self.state.run_n_steps(count)?;
+            }
+            Command::Snapshot => return Ok(Some(self.snapshot())),
+        }
+        Ok(None)
+    }
+
+    /// Parses and applies one line of a command stream, returning the line to answer with: the
+    /// snapshot asked for or the error that occurred. Blank lines are skipped.
+    pub fn apply_line(&mut self, line: &str) -> Option<String> {
+        if line.trim().is_empty() {
+            return None;
+        }
+        let applied = serde_json::from_str(line)
+            .map_err(io::Error::from)
+            .and_then(|e| self.apply(e));
+        match applied {
+            Ok(Some(snapshot)) => Some(serde_json::to_string(&snapshot).unwrap()),
+            Ok(None) => None,
+            Err(e) => Some(json!({ "error": e.to_string() }).to_string()),
+        }
+    }
+}
+
+/// Runs the simulation `steps` at a time, applying the commands read from `input` in between,
+/// until `input` ends.
+///
+/// Commands are read on their own thread, so the simulation keeps running while none arrive.
+/// Without nodes it waits for the next command instead.
+pub fn run<R, W>(stream: &mut Stream, input: R, mut output: W, steps: usize) -> io::Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write,
+{
+    let (sender, receiver) = mpsc::channel();
+    let reader = thread::spawn(move || -> io::Result<()> {
+        for line in input.lines() {
+            if sender.send(line?).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+    loop {
+        let line = if stream.state().nodes().is_empty() {
+            receiver.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            receiver.try_recv()
+        };
+        match line {
+            Ok(line) => {
+                if let Some(answer) = stream.apply_line(&line) {
+                    writeln!(output, "{answer}")?;
+                    output.flush()?;
+                }
+            }
+            Err(TryRecvError::Empty) => {
+                stream.state().run_n_steps(steps)?;
+            }
+            Err(TryRecvError::Disconnected) => break,
+        }
+    }
+    reader.join().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::Value;
+
+    use crate::{
+        model::Graph,
+        sim::{LayoutParams, SimulationState},
+    };
+
+    use super::{run, Command, Stream};
+
+    const SCRIPT: &str = r#"
+{"op": "add_node", "id": 1, "x": 0, "y": 0}
+{"op": "add_node", "id": 2, "x": 10, "y": 0, "label": "Hamburg"}
+{"op": "add_node", "id": 3}
+{"op": "add_edge", "from": 1, "to": 2, "weight": 2}
+{"op": "add_edge", "from": 2, "to": 3}
+{"op": "pin", "id": 1, "x": 5, "y": 5}
+not a command
+{"op": "remove_node", "id": 7}
+{"op": "set_weight", "from": 2, "to": 1, "weight": 0.5}
+{"op": "step", "count": 10}
+{"op": "remove_node", "id": 3}
+{"op": "snapshot"}
+"#;
+
+    #[test]
+    fn parses_commands() {
+        let command: Command =
+            serde_json::from_str(r#"{"op": "add_edge", "from": 1, "to": 2}"#).unwrap();
+        assert_eq!(
+            command,
+            Command::AddEdge {
+                from: 1,
+                to: 2,
+                weight: 1.0
+            }
+        );
+        assert!(serde_json::from_str::<Command>(r#"{"op": "explode"}"#).is_err());
+    }
+
+    #[test]
+    fn applies_scripted_commands() {
+        let mut stream = Stream::new(SimulationState::from_graph(
+            Graph::default(),
+            LayoutParams::default(),
+        ));
+        let mut output = Vec::new();
+        run(&mut stream, Cursor::new(SCRIPT), &mut output, 5).unwrap();
+
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|e| serde_json::from_str(e).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0]["error"].is_string());
+        assert_eq!(lines[1]["error"], "node 7 does not exist");
+        let snapshot = &lines[2];
+        assert!(snapshot["step"].as_u64().unwrap() >= 10);
+        let nodes = snapshot["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 2);
+        // The pinned node stays where it was put while the other one moved.
+        assert_eq!(
+            (nodes[0]["x"].as_f64(), nodes[0]["y"].as_f64()),
+            (Some(5.0), Some(5.0))
+        );
+        assert_ne!(nodes[1]["x"].as_f64(), Some(10.0));
+
+        let relations = stream.state().relations();
+        assert_eq!(relations.len(), 1);
+        assert_eq!((relations[0].from.id(), relations[0].weight), (1, 0.5));
+    }
+
+    #[test]
+    fn keeps_nodes_free_of_removed_relations() {
+        let mut stream = Stream::new(SimulationState::from_graph(
+            Graph::default(),
+            LayoutParams::default(),
+        ));
+        for line in [
+            r#"{"op": "add_node", "id": 1}"#,
+            r#"{"op": "add_node", "id": 2}"#,
+            r#"{"op": "add_node", "id": 3}"#,
+            r#"{"op": "add_edge", "from": 1, "to": 2}"#,
+            r#"{"op": "add_edge", "from": 2, "to": 3}"#,
+        ] {
+            assert_eq!(stream.apply_line(line), None);
+        }
+        let error = stream
+            .apply_line(r#"{"op": "add_edge", "from": 2, "to": 2}"#)
+            .unwrap();
+        assert!(error.contains("cannot be related to itself"), "{error}");
+        assert!(stream
+            .apply_line(r#"{"op": "add_node", "id": 4, "weight": 0}"#)
+            .is_some());
+
+        // Relations still held elsewhere must not pull on the nodes anymore.
+        let held = stream.state().relations().to_vec();
+        assert_eq!(
+            stream.apply_line(r#"{"op": "set_weight", "from": 2, "to": 1, "weight": 0.5}"#),
+            None
+        );
+        let weights: Vec<f32> = stream
+            .node(1)
+            .unwrap()
+            .relations()
+            .iter()
+            .map(|e| e.weight)
+            .collect();
+        assert_eq!(weights, vec![0.5]);
+        assert_eq!(stream.apply_line(r#"{"op": "remove_node", "id": 3}"#), None);
+        assert_eq!(stream.node(2).unwrap().relations().len(), 1);
+        assert_eq!(
+            stream.apply_line(r#"{"op": "remove_edge", "from": 1, "to": 2}"#),
+            None
+        );
+        assert!(stream.node(1).unwrap().relations().is_empty());
+        assert_eq!(held.len(), 2);
+
+        // The index follows the removal.
+        assert_eq!(stream.node(2).unwrap().id(), 2);
+        assert!(stream.node(3).is_err());
+    }
+}
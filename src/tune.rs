@@ -0,0 +1,223 @@
+use std::{fmt, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adjacency::Adjacency,
+    builder::{GraphBuilder, INITIAL_EXTENT},
+    export::Layout,
+    metrics,
+    model::{Node, Relation},
+    sim::{LayoutParams, SimulationState},
+};
+
+/// Size, density and weight statistics the parameters are derived from.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GraphStats {
+    pub nodes: usize,
+    pub edges: usize,
+    pub mean_degree: f32,
+    pub density: f32,
+    pub mean_node_weight: f32,
+    pub mean_edge_weight: f32,
+}
+
+impl GraphStats {
+    pub fn new(nodes: &[Arc<Node>], relations: &[Arc<Relation>]) -> Self {
+        let n = nodes.len();
+        let m = relations.len();
+        let mean = |sum: f32, count: usize| if count == 0 { 1.0 } else { sum / count as f32 };
+        Self {
+            nodes: n,
+            edges: m,
+            mean_degree: if n == 0 {
+                0.0
+            } else {
+                2.0 * m as f32 / n as f32
+            },
+            density: if n < 2 {
+                0.0
+            } else {
+                2.0 * m as f32 / (n as f32 * (n - 1) as f32)
+            },
+            mean_node_weight: mean(nodes.iter().map(|e| e.weight.abs()).sum(), n),
+            mean_edge_weight: mean(relations.iter().map(|e| e.weight.abs()).sum(), m),
+        }
+    }
+}
+
+/// Quality metric minimized by [`search`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Objective {
+    Stress,
+    EdgeCrossings,
+}
+
+impl Objective {
+    fn score(self, layout: &Layout) -> f32 {
+        let degenerate = layout
+            .nodes
+            .iter()
+            .any(|e| !e.x.is_finite() || !e.y.is_finite());
+        // A layout with every node on the same spot has neither stress nor crossings left.
+        let collapsed = layout.nodes.len() > 1
+            && layout
+                .nodes
+                .iter()
+                .all(|e| (e.x, e.y) == (layout.nodes[0].x, layout.nodes[0].y));
+        if degenerate || collapsed {
+            return f32::INFINITY;
+        }
+        match self {
+            Objective::Stress => metrics::stress(layout, &Adjacency::from_layout(layout)),
+            Objective::EdgeCrossings => metrics::edge_crossings(layout) as f32,
+        }
+    }
+}
+
+/// Chosen parameters together with the figures that led to them.
+#[derive(Copy, Clone, Debug)]
+pub struct TuneReport {
+    pub stats: GraphStats,
+    pub params: LayoutParams,
+    /// Score of the chosen parameters if a search was run.
+    pub score: Option<f32>,
+}
+
+impl fmt::Display for TuneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Nodes => {} Edges => {} Density => {} Spring => {} Coloumb => {} Time => {}",
+            self.stats.nodes,
+            self.stats.edges,
+            self.stats.density,
+            self.params.spring_scale,
+            self.params.coloumb_scale,
+            self.params.time_delta
+        )?;
+        if let Some(score) = self.score {
+            write!(f, " Score => {score}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Derives parameters from the graph statistics alone.
+///
+/// The coloumb scale normalizes the node weights. The spring scale is chosen so that springs and
+/// repulsion balance at the edge length a uniform spread over the initial area would have, and
+/// the time delta limits a step to a fraction of that length.
+pub fn heuristic(stats: &GraphStats) -> LayoutParams {
+    let ideal_length = INITIAL_EXTENT / (stats.nodes.max(1) as f32).sqrt();
+    let coloumb_scale = 1.0 / stats.mean_node_weight.max(f32::EPSILON).powi(2);
+    let spring_root = 1.0
+        / (stats.mean_edge_weight.max(f32::EPSILON)
+            * ideal_length.powi(3)
+            * stats.mean_degree.max(1.0));
+    LayoutParams {
+        spring_scale: spring_root.powi(2),
+        coloumb_scale,
+        time_delta: (0.1 * ideal_length.powi(3)).sqrt(),
+        ..LayoutParams::default()
+    }
+}
+
+const SPRING_FACTORS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const COLOUMB_FACTORS: [f32; 3] = [0.5, 1.0, 2.0];
+
+/// Refines the heuristic parameters by running short simulations around them, all starting from
+/// the positions in `layout`, and keeping the ones with the lowest score.
+pub fn search(layout: &Layout, steps: usize, objective: Objective) -> std::io::Result<TuneReport> {
+    let probe = GraphBuilder::from_layout(layout)?.build();
+    let stats = GraphStats::new(&probe.nodes, &probe.relations);
+    let base = heuristic(&stats);
+
+    let mut best = (f32::INFINITY, base);
+    for spring in SPRING_FACTORS {
+        for coloumb in COLOUMB_FACTORS {
+            let params = LayoutParams {
+                spring_scale: base.spring_scale * spring,
+                coloumb_scale: base.coloumb_scale * coloumb,
+                ..base
+            };
+            let state =
+                SimulationState::from_graph(GraphBuilder::from_layout(layout)?.build(), params);
+            state.run_n_steps(steps)?;
+            let score = objective.score(&state.layout());
+            if score < best.0 {
+                best = (score, params);
+            }
+        }
+    }
+
+    Ok(TuneReport {
+        stats,
+        params: best.1,
+        score: best.0.is_finite().then_some(best.0),
+    })
+}
+
+/// Parameters derived from the statistics of the graph without running a search.
+pub fn tune(nodes: &[Arc<Node>], relations: &[Arc<Relation>]) -> TuneReport {
+    let stats = GraphStats::new(nodes, relations);
+    TuneReport {
+        stats,
+        params: heuristic(&stats),
+        score: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{builder::GraphBuilder, sim::SimulationState};
+
+    use super::{search, tune, Objective};
+
+    fn ring(n: usize) -> GraphBuilder {
+        let mut builder = GraphBuilder::new();
+        for i in 0..n {
+            builder.add_node(i, 1.0).unwrap();
+        }
+        for i in 0..n {
+            builder.add_edge(i, (i + 1) % n, 1.0).unwrap();
+        }
+        builder
+    }
+
+    #[test]
+    fn heuristic_keeps_layout_finite() {
+        let graph = ring(30).build();
+        let report = tune(&graph.nodes, &graph.relations);
+        assert_eq!(report.stats.edges, 30);
+        assert!((report.stats.mean_degree - 2.0).abs() < 1.0E-6);
+
+        let state = SimulationState::from_graph(graph, report.params);
+        state.run_n_steps(200).unwrap();
+        assert!(state
+            .layout()
+            .nodes
+            .iter()
+            .all(|e| e.x.is_finite() && e.y.is_finite()));
+    }
+
+    #[test]
+    fn search_reports_score() {
+        let graph = ring(12).build();
+        let layout = crate::export::Layout::from_graph(&graph.nodes, &graph.relations);
+        let report = search(&layout, 50, Objective::Stress).unwrap();
+        let score = report.score.unwrap();
+        assert!((0.0..=1.0).contains(&score), "Score is {score}");
+    }
+
+    #[test]
+    fn collapsed_layout_scores_worst() {
+        let graph = ring(4).build();
+        let mut layout = crate::export::Layout::from_graph(&graph.nodes, &graph.relations);
+        for node in &mut layout.nodes {
+            (node.x, node.y) = (3.0, 3.0);
+        }
+        assert_eq!(Objective::Stress.score(&layout), f32::INFINITY);
+        assert_eq!(Objective::EdgeCrossings.score(&layout), f32::INFINITY);
+    }
+}